@@ -0,0 +1,75 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use nametag::{
+    app::to_empty_state,
+    filename::{generate_with_salt, parse::PreparedSchema},
+    schema::compile_schema,
+};
+
+const SCHEMA: &str = r#"schema "-" "_"
+  [ category "Media" (exactly 1) ['art', 'photo'/'ph', 'video'/'v']
+  , category "People" (at_least 0) ['nate', 'kim', 'sam', 'alex']
+  , category "Place" (at_most 2) ['home', 'park', 'beach', 'city']
+  ]"#;
+
+fn filenames(n: usize) -> Vec<String> {
+    let schema = compile_schema(SCHEMA).unwrap();
+    (0..n)
+        .map(|i| {
+            let mut state = to_empty_state(&schema);
+            state[0].1[i % 3].1 = true;
+            for (j, kw) in state[1].1.iter_mut().enumerate() {
+                kw.1 = i & (1 << j) != 0;
+            }
+            state[2].1[i % 4].1 = i % 5 != 0;
+            generate_with_salt(&schema, &state, "ABC123").unwrap()
+        })
+        .collect()
+}
+
+fn parse(c: &mut Criterion) {
+    let schema = compile_schema(SCHEMA).unwrap();
+    let names = filenames(10_000);
+
+    // Schema::parse and parse_into scan the schema for each keyword rather than
+    // building PreparedSchema's index per call
+    c.bench_function("parse 10k", |b| {
+        b.iter(|| {
+            for name in &names {
+                black_box(schema.parse(name).unwrap());
+            }
+        })
+    });
+
+    c.bench_function("parse_into 10k", |b| {
+        let mut buf = to_empty_state(&schema);
+        b.iter(|| {
+            for name in &names {
+                schema.parse_into(name, &mut buf).unwrap();
+                black_box(&buf);
+            }
+        })
+    });
+
+    c.bench_function("prepared parse 10k", |b| {
+        let prepared = PreparedSchema::new(&schema);
+        b.iter(|| {
+            for name in &names {
+                black_box(prepared.parse(name).unwrap());
+            }
+        })
+    });
+
+    c.bench_function("prepared parse_into 10k", |b| {
+        let prepared = PreparedSchema::new(&schema);
+        let mut buf = to_empty_state(&schema);
+        b.iter(|| {
+            for name in &names {
+                prepared.parse_into(name, &mut buf).unwrap();
+                black_box(&buf);
+            }
+        })
+    });
+}
+
+criterion_group!(benches, parse);
+criterion_main!(benches);
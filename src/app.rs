@@ -1,6 +1,7 @@
 use crate::{
     error::{Error, Result},
-    filename, fs,
+    filename::{self, SaltMode},
+    fs,
     schema::Schema,
     State,
 };
@@ -128,19 +129,28 @@ impl AppConfig {
     }
 
     fn gen_id(&mut self) {
-        self.file_id = filename::gen_rand_id(&mut self.rng);
+        // validation rejects schemas where the placeholder is the only possible salt
+        self.file_id = self
+            .schema
+            .salt
+            .generate_except(&mut self.rng, &self.schema.empty)
+            .unwrap_or_else(|| self.schema.salt.generate(&mut self.rng));
     }
 
     fn mk_filename(&self) -> StdResult<String, String> {
-        match filename::generate(&self.schema, &self.ui_state) {
+        let name = match self.schema.salt.mode {
+            SaltMode::Random => {
+                filename::generate_with_salt(&self.schema, &self.ui_state, &self.file_id)
+            }
+            SaltMode::Checksum => filename::generate_checksummed(&self.schema, &self.ui_state),
+        };
+        match name {
             Ok(name) => {
-                let id = self.file_id.clone();
-                let delim = self.schema.delim.clone();
                 let ext = match self.active_file().extension() {
                     Some(ext) => format!(".{}", ext.to_string_lossy()),
                     None => String::new(),
                 };
-                Ok(format!("{id}{delim}{name}{ext}"))
+                Ok(format!("{name}{ext}"))
             }
             Err(e) => Err(e.to_string()),
         }
@@ -156,7 +166,7 @@ impl AppConfig {
         &self.files[self.active]
     }
 
-    fn load_active(&mut self) -> egui::Image {
+    fn load_active(&mut self) -> egui::Image<'_> {
         let uri = Self::to_uri(self.active_file());
         // skip the io if this uri is already in the cache
         if self.ctx.try_load_bytes(&uri).is_ok() {
@@ -257,9 +267,13 @@ impl eframe::App for AppConfig {
 
                 self.ui_state.iter_mut().for_each(|cat| {
                     ui.label(cat.0.name.clone());
-                    cat.1.iter_mut().for_each(|kw| {
+                    // drafts can still be parsed but aren't offered for new filenames
+                    cat.1.iter_mut().filter(|kw| !kw.0.draft).for_each(|kw| {
                         let name = kw.0.name.clone();
-                        ui.checkbox(&mut kw.1, name);
+                        let checkbox = ui.checkbox(&mut kw.1, name);
+                        if let Some(description) = &kw.0.description {
+                            checkbox.on_hover_text(description);
+                        }
                     })
                 })
             });
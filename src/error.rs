@@ -1,4 +1,7 @@
-use crate::schema::{SchemaParseError, SchemaTypeCheckError};
+use crate::{
+    filename::GenerateFilenameError,
+    schema::{SchemaParseError, SchemaTypeCheckError},
+};
 use std::{error::Error as StdError, fmt, io, result::Result as StdResult};
 use tracing::subscriber::SetGlobalDefaultError;
 use Error::*;
@@ -18,6 +21,7 @@ pub enum Error {
     FailedToReadContents(io::Error),
     LoggerFailed(SetGlobalDefaultError),
     PathErr(io::Error),
+    Generate(GenerateFilenameError),
 }
 
 impl fmt::Display for Error {
@@ -37,6 +41,7 @@ impl fmt::Display for Error {
             LoggerFailed(e) => write!(f, "Failed to set up logger: {e}"),
             FailedToReadContents(e) => write!(f, "Failed read file contents: {e}"),
             PathErr(e) => write!(f, "Issue with path: {e}"),
+            Generate(e) => write!(f, "{e}"),
         }
     }
 }
@@ -55,6 +60,7 @@ impl StdError for Error {
             LoggerFailed(e) => Some(e),
             FailedToReadContents(e) => Some(e),
             PathErr(e) => Some(e),
+            Generate(e) => Some(e),
         }
     }
 }
@@ -76,3 +82,9 @@ impl From<eframe::Error> for Error {
         Eframe(e)
     }
 }
+
+impl From<GenerateFilenameError> for Error {
+    fn from(e: GenerateFilenameError) -> Self {
+        Generate(e)
+    }
+}
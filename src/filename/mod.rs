@@ -1,18 +1,17 @@
 pub mod parse;
 
-use crate::schema::{
-    Category,
-    Requirement::{self, *},
-    Schema,
-};
+use crate::schema::{Category, Fnv1a, Keyword, Requirement, Schema};
 use crate::State;
 use core::fmt;
-use rand::Rng;
 use rand::{
     distributions::{Distribution, Uniform},
-    rngs::ThreadRng,
+    Rng,
+};
+use std::{
+    collections::{HashMap, HashSet},
+    error::Error as StdError,
+    hash::Hasher,
 };
-use std::error::Error as StdError;
 use GenerateFilenameError::*;
 
 #[derive(Clone, Debug, PartialEq, Eq)]
@@ -22,73 +21,784 @@ pub enum GenerateFilenameError {
         expected: Requirement,
         got: usize,
     },
+    InvalidSalt(String),
+    /// indices of every selection in a batch that generated the same filename
+    Collision(Vec<usize>),
+    /// the schema requires at least one tag but none were selected
+    NoTags,
+    /// the id isn't a keyword of the named category, or there's no category by that name
+    NotInCategory {
+        category: String,
+        id: String,
+    },
+    /// the keyword is a draft, which can be parsed but not used for new filenames
+    DraftKeyword {
+        category: String,
+        id: String,
+    },
+    /// the category has more tags than the schema allows any category to have
+    TooManyTags {
+        category: String,
+        max: usize,
+        got: usize,
+    },
 }
 
 impl fmt::Display for GenerateFilenameError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            Self::RequirementMismatch { category, expected, got } => write!(f, "Category {} has a tag requirement of {expected}, but there were {got} keywords found.", category.name)
+            Self::RequirementMismatch { category, expected, got } => write!(f, "Category {} has a tag requirement of {expected}, but there were {got} keywords found.", category.name),
+            Self::InvalidSalt(salt) => write!(f, "Salt \"{salt}\" does not match the schema's salt spec."),
+            Self::Collision(indices) => write!(f, "Selections {indices:?} generate the same filename."),
+            Self::NoTags => write!(f, "The schema requires at least one tag."),
+            Self::NotInCategory { category, id } => write!(f, "Keyword id \"{id}\" is not in category \"{category}\"."),
+            Self::DraftKeyword { category, id } => write!(f, "Keyword id \"{id}\" in category \"{category}\" is a draft and can't be used in new filenames."),
+            Self::TooManyTags { category, max, got } => write!(f, "Category \"{category}\" has {got} tags but the schema allows at most {max}."),
         }
     }
 }
 
 impl StdError for GenerateFilenameError {}
 
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FilenameParseError {
+    MissingSalt,
+    InvalidSalt(String),
+    DelimiterInSalt(String),
+    UnexpectedTag(String),
+    UnexpectedEnd,
+    /// a checksum salt doesn't match the tags that follow it
+    ChecksumMismatch {
+        expected: String,
+        got: String,
+    },
+    /// the segment after the salt isn't this schema's version
+    VersionMismatch {
+        expected: u32,
+        got: String,
+    },
+    RequirementMismatch {
+        category: Category,
+        expected: Requirement,
+        got: usize,
+    },
+    /// the schema requires at least one tag but the filename has none
+    NoTags,
+    /// the parsed tags can't be generated again, which checking a checksum or
+    /// comparing against the canonical filename needs
+    Generate(GenerateFilenameError),
+}
+
+impl fmt::Display for FilenameParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MissingSalt => write!(f, "Filename does not start with a salt."),
+            Self::InvalidSalt(salt) => write!(f, "Salt \"{salt}\" does not match the schema's salt spec."),
+            Self::DelimiterInSalt(salt) => write!(f, "Salt \"{salt}\" contains the schema's delimiter."),
+            Self::UnexpectedTag(tag) => write!(f, "Unexpected tag \"{tag}\"."),
+            Self::UnexpectedEnd => write!(f, "Filename ended before every category was found."),
+            Self::ChecksumMismatch { expected, got } => write!(f, "Salt \"{got}\" doesn't match the checksum of the tags \"{expected}\"."),
+            Self::VersionMismatch { expected, got } => write!(f, "Expected schema version v{expected}, but found \"{got}\"."),
+            Self::RequirementMismatch { category, expected, got } => write!(f, "Category {} has a tag requirement of {expected}, but there were {got} keywords found.", category.name),
+            Self::NoTags => write!(f, "The schema requires at least one tag."),
+            Self::Generate(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl StdError for FilenameParseError {}
+
+impl From<GenerateFilenameError> for FilenameParseError {
+    fn from(e: GenerateFilenameError) -> Self {
+        FilenameParseError::Generate(e)
+    }
+}
+
+/// separates the category name from each tag in labeled filenames.
+pub const LABEL_DELIM: &str = ".";
+
 pub fn generate(schema: &Schema, state: &State) -> Result<String, GenerateFilenameError> {
+    check_drafts(state)?;
+    write_tags(schema, state)
+}
+
+/// errors on the first selected draft keyword.
+fn check_drafts(state: &State) -> Result<(), GenerateFilenameError> {
+    for (cat, kws) in state {
+        if let Some((kw, _)) = kws.iter().find(|(kw, tf)| *tf && kw.draft) {
+            return Err(DraftKeyword {
+                category: cat.name.clone(),
+                id: kw.id.clone(),
+            });
+        }
+    }
+    Ok(())
+}
+
+/// the tag portion of a filename. Unlike `generate` it allows drafts,
+/// so parsing can rebuild filenames that already exist.
+pub(crate) fn write_tags(schema: &Schema, state: &State) -> Result<String, GenerateFilenameError> {
+    schema.validate_state(state)?;
     let mut name = String::new();
     for (cat, kws) in state {
         let ids: Vec<String> = kws
             .iter()
             .filter_map(|(kw, tf)| if *tf { Some(kw.id.clone()) } else { None })
             .collect();
-        match cat.requirement {
-            expected @ Exactly(n) if ids.len() != (n as usize) => Err(RequirementMismatch {
-                category: cat.clone(),
-                expected,
-                got: ids.len(),
-            }),
-            expected @ AtMost(n) if ids.len() > (n as usize) => Err(RequirementMismatch {
-                category: cat.clone(),
-                expected,
-                got: ids.len(),
-            }),
-            expected @ AtLeast(n) if ids.len() < (n as usize) => Err(RequirementMismatch {
-                category: cat.clone(),
-                expected,
-                got: ids.len(),
-            }),
-            _ => {
-                if ids.is_empty() {
-                    name.push_str(&schema.empty);
-                    name.push_str(&schema.delim)
-                }
-                for id in ids {
-                    name.push_str(&id);
-                    name.push_str(&schema.delim)
-                }
-                Ok(())
+        let sep = cat.intra_delim.as_ref().unwrap_or(&schema.delim);
+        let mut push_tag = |i: usize, tag: &str| {
+            if i > 0 {
+                sep.push_to(&mut name);
             }
-        }?;
+            if schema.labeled {
+                name.push_str(&cat.name);
+                name.push_str(LABEL_DELIM);
+            }
+            name.push_str(&schema.escape_tag(tag));
+        };
+        if ids.is_empty() {
+            if schema.empty_mode == EmptyMode::Collapse {
+                continue;
+            }
+            push_tag(0, &schema.empty);
+        }
+        for (i, id) in ids.iter().enumerate() {
+            push_tag(i, id);
+        }
+        schema.delim.push_to(&mut name);
+    }
+    // a single placeholder stands in when every category collapsed away
+    if let (true, Some((cat, _))) = (name.is_empty(), state.first()) {
+        if schema.labeled {
+            name.push_str(&cat.name);
+            name.push_str(LABEL_DELIM);
+        }
+        name.push_str(&schema.empty);
+        schema.delim.push_to(&mut name);
     }
 
     // remove the last delimeter added
-    name.pop();
+    name.truncate(name.len().saturating_sub(schema.delim.len()));
     Ok(name)
 }
 
-pub fn gen_rand_id(rng: &mut ThreadRng) -> String {
-    (0..6)
-        .map(|_| rng.sample(IDChars) as char)
-        .collect::<String>()
+/// lists each category with its selected keyword names, skipping empty categories.
+/// e.g. "Media: art; People: nate, kim"
+pub fn describe_selection(state: &State) -> String {
+    state
+        .iter()
+        .filter_map(|(cat, kws)| {
+            let names: Vec<&str> = kws
+                .iter()
+                .filter_map(|(kw, tf)| if *tf { Some(kw.name.as_str()) } else { None })
+                .collect();
+            if names.is_empty() {
+                None
+            } else {
+                Some(format!("{}: {}", cat.name, names.join(", ")))
+            }
+        })
+        .collect::<Vec<String>>()
+        .join("; ")
+}
+
+/// whether both states select the same keyword ids in the same categories. States
+/// never hold a salt, so filenames that differ only by salt parse to the same selection.
+pub fn same_selection(a: &State, b: &State) -> bool {
+    let selection = |state: &State| -> Vec<(String, Vec<String>)> {
+        state
+            .iter()
+            .map(|(cat, kws)| {
+                let ids = kws
+                    .iter()
+                    .filter_map(|(kw, tf)| if *tf { Some(kw.id.clone()) } else { None })
+                    .collect();
+                (cat.name.clone(), ids)
+            })
+            .collect()
+    };
+    selection(a) == selection(b)
+}
+
+/// number of categories, matched by name, whose selected keyword ids differ between the
+/// states. Swapping one tag for another in a category counts once. Like `same_selection`,
+/// salts don't factor in.
+pub fn selection_distance(a: &State, b: &State) -> usize {
+    let selected = |state: &State| -> HashMap<String, HashSet<String>> {
+        state
+            .iter()
+            .map(|(cat, kws)| {
+                let ids = kws
+                    .iter()
+                    .filter(|(_, tf)| *tf)
+                    .map(|(kw, _)| kw.id.clone())
+                    .collect();
+                (cat.name.clone(), ids)
+            })
+            .collect()
+    };
+    let (a, b) = (selected(a), selected(b));
+    let none = HashSet::new();
+    a.keys()
+        .chain(b.keys().filter(|name| !a.contains_key(*name)))
+        .filter(|name| a.get(*name).unwrap_or(&none) != b.get(*name).unwrap_or(&none))
+        .count()
+}
+
+/// generates a filename from the selected tags led by the given salt.
+pub fn generate_with_salt(
+    schema: &Schema,
+    state: &State,
+    salt: &str,
+) -> Result<String, GenerateFilenameError> {
+    check_drafts(state)?;
+    write_with_salt(schema, state, salt)
+}
+
+/// `generate_with_salt` allowing drafts, as with `write_tags`.
+pub(crate) fn write_with_salt(
+    schema: &Schema,
+    state: &State,
+    salt: &str,
+) -> Result<String, GenerateFilenameError> {
+    if !schema.salt.is_valid(salt) {
+        return Err(InvalidSalt(salt.to_string()));
+    }
+    let name = write_tags(schema, state)?;
+    if schema.salt.mode == SaltMode::Checksum && salt != schema.salt.checksum(&name) {
+        return Err(InvalidSalt(salt.to_string()));
+    }
+    let name = match schema.version_segment() {
+        Some(version) => format!("{version}{}{name}", schema.delim),
+        None => name,
+    };
+    match schema.salt.position {
+        SaltPosition::Leading => Ok(format!("{salt}{}{name}", schema.delim)),
+        SaltPosition::Trailing => Ok(format!("{name}{}{salt}", schema.delim)),
+    }
+}
+
+/// generates a filename from (category name, keyword id) pairs instead of a full selection.
+/// Freeform categories take any id.
+pub fn generate_from_pairs(
+    schema: &Schema,
+    pairs: &[(&str, &str)],
+    salt: &str,
+) -> Result<String, GenerateFilenameError> {
+    let mut state = crate::app::to_empty_state(schema);
+    for (category, id) in pairs {
+        let not_in_category = || NotInCategory {
+            category: category.to_string(),
+            id: id.to_string(),
+        };
+        let (cat, kws) = state
+            .iter_mut()
+            .find(|(cat, _)| cat.name == *category)
+            .ok_or_else(not_in_category)?;
+        match kws.iter_mut().find(|(kw, _)| kw.id == *id) {
+            Some((_, tf)) => *tf = true,
+            None if cat.freeform && !id.is_empty() => kws.push((
+                Keyword {
+                    name: id.to_string(),
+                    id: id.to_string(),
+                    description: None,
+                    draft: false,
+                },
+                true,
+            )),
+            None => return Err(not_in_category()),
+        }
+    }
+    generate_with_salt(schema, &state, salt)
+}
+
+/// generates a filename led by the checksum of its tags.
+pub fn generate_checksummed(
+    schema: &Schema,
+    state: &State,
+) -> Result<String, GenerateFilenameError> {
+    let salt = schema.salt.checksum(&generate(schema, state)?);
+    generate_with_salt(schema, state, &salt)
+}
+
+/// generates a filename for each salt and selection pair. Pairs that generate
+/// the same filename as another pair in the batch are reported as collisions.
+pub fn generate_batch(
+    schema: &Schema,
+    selections: &[(String, State)],
+) -> Vec<Result<String, GenerateFilenameError>> {
+    let names: Vec<Result<String, GenerateFilenameError>> = selections
+        .iter()
+        .map(|(salt, state)| generate_with_salt(schema, state, salt))
+        .collect();
+
+    let mut indices: HashMap<&str, Vec<usize>> = HashMap::new();
+    for (i, name) in names.iter().enumerate() {
+        if let Ok(name) = name {
+            indices.entry(name).or_default().push(i);
+        }
+    }
+    let collisions: Vec<Vec<usize>> = indices.into_values().filter(|xs| xs.len() > 1).collect();
+
+    let mut names = names;
+    for xs in collisions {
+        for &i in &xs {
+            names[i] = Err(Collision(xs.clone()));
+        }
+    }
+    names
+}
+
+/// lists the keyword ids of each category that are never selected by any of the
+/// filenames, skipping categories where every id is used. Filenames that don't
+/// parse are ignored.
+pub fn unused_keywords(schema: &Schema, filenames: &[&str]) -> Vec<(String, Vec<String>)> {
+    let mut used: Vec<Vec<bool>> = schema
+        .categories
+        .iter()
+        .map(|(_, kws)| vec![false; kws.len()])
+        .collect();
+    for filename in filenames {
+        let Ok(state) = schema.parse(filename) else {
+            continue;
+        };
+        // freeform tags are appended past the schema's keywords, so zip drops them
+        for ((_, kws), used) in state.iter().zip(used.iter_mut()) {
+            for ((_, tf), used) in kws.iter().zip(used.iter_mut()) {
+                *used |= *tf;
+            }
+        }
+    }
+
+    schema
+        .categories
+        .iter()
+        .zip(used)
+        .filter_map(|((cat, kws), used)| {
+            let unused: Vec<String> = kws
+                .iter()
+                .zip(used)
+                .filter_map(|(kw, used)| if used { None } else { Some(kw.id.clone()) })
+                .collect();
+            if unused.is_empty() {
+                None
+            } else {
+                Some((cat.name.clone(), unused))
+            }
+        })
+        .collect()
+}
+
+/// how the salt leading each filename is chosen.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum SaltMode {
+    /// random characters so renamed files don't collide
+    #[default]
+    Random,
+    /// a hash of the tags so identical selections get identical filenames
+    Checksum,
+}
+
+/// how categories without any tags are written.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum EmptyMode {
+    /// each empty category is written as the empty placeholder
+    #[default]
+    Placeholder,
+    /// empty categories are left out. Filenames are shorter, but a tag's position
+    /// no longer says which category it's in.
+    Collapse,
 }
 
-struct IDChars;
+/// which end of the filename the salt is on.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum SaltPosition {
+    #[default]
+    Leading,
+    Trailing,
+}
+
+/// describes the salt that leads every filename so renamed files don't collide.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct SaltSpec {
+    pub len: usize,
+    /// must only contain ascii characters
+    pub charset: &'static str,
+    pub mode: SaltMode,
+    pub position: SaltPosition,
+}
+
+impl Default for SaltSpec {
+    fn default() -> Self {
+        SaltSpec {
+            len: 6,
+            charset: "ABCDEFGHIJKLMNPQRSTUVWXYZ123456789",
+            mode: SaltMode::default(),
+            position: SaltPosition::default(),
+        }
+    }
+}
+
+impl SaltSpec {
+    /// a salt derived from the tag portion of a filename using FNV-1a, which is
+    /// stable across platforms and compiler versions.
+    pub fn checksum(&self, tags: &str) -> String {
+        let charset = self.charset.as_bytes();
+        (0..self.len)
+            .map(|i| {
+                let mut hasher = Fnv1a::default();
+                hasher.write(tags.as_bytes());
+                hasher.write(&[i as u8]);
+                let hash = hasher.finish();
+                charset[(hash % charset.len() as u64) as usize] as char
+            })
+            .collect()
+    }
+
+    pub fn generate<R: Rng + ?Sized>(&self, rng: &mut R) -> String {
+        (0..self.len).map(|_| rng.sample(self)).collect()
+    }
+
+    /// generates salts until one isn't `avoid`, such as the empty placeholder.
+    /// Returns `None` when `avoid` is the only salt this spec can make.
+    pub fn generate_except<R: Rng + ?Sized>(&self, rng: &mut R, avoid: &str) -> Option<String> {
+        let mut chars = self.charset.bytes();
+        let first = chars.next();
+        let one_salt = self.len == 0 || chars.all(|c| Some(c) == first);
+        if one_salt && self.is_valid(avoid) {
+            return None;
+        }
+        loop {
+            let salt = self.generate(rng);
+            if salt != avoid {
+                return Some(salt);
+            }
+        }
+    }
+
+    /// swaps the case of each character that's only in the charset in the other case.
+    pub fn match_case(&self, salt: &str) -> String {
+        salt.chars()
+            .map(|c| {
+                if self.charset.contains(c) {
+                    return c;
+                }
+                [c.to_ascii_lowercase(), c.to_ascii_uppercase()]
+                    .into_iter()
+                    .find(|x| self.charset.contains(*x))
+                    .unwrap_or(c)
+            })
+            .collect()
+    }
+
+    pub fn is_valid(&self, salt: &str) -> bool {
+        salt.chars().count() == self.len && salt.chars().all(|c| self.charset.contains(c))
+    }
 
-impl Distribution<u8> for IDChars {
-    fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> u8 {
-        const RANGE: usize = 25 + 9;
-        const CHARSET: &[u8] = b"ABCDEFGHIJKLMNPQRSTUVWXYZ123456789";
-        let range = Uniform::new(0, RANGE);
-        CHARSET[range.sample(rng)]
+    /// chance that at least two of `n` generated salts are equal, using the
+    /// birthday approximation `1 - e^(-n(n-1) / 2N)` over the `N` possible salts.
+    pub fn collision_probability(&self, n: usize) -> f64 {
+        let space = (self.charset.len() as f64).powi(self.len as i32);
+        let pairs = n as f64 * n.saturating_sub(1) as f64 / 2.0;
+        -(-pairs / space).exp_m1()
     }
 }
+
+impl Distribution<char> for SaltSpec {
+    fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> char {
+        let range = Uniform::new(0, self.charset.len());
+        self.charset.as_bytes()[range.sample(rng)] as char
+    }
+}
+
+#[test]
+fn generate_fixed_salt() {
+    let schema = crate::schema::compile_schema(crate::schema::TEST_SCHEMA).unwrap();
+    let mut state = crate::app::to_empty_state(&schema);
+    state[0].1[1].1 = true;
+    assert_eq!(
+        generate_with_salt(&schema, &state, "ABC123"),
+        Ok("ABC123-ph-_".to_string())
+    );
+    assert_eq!(
+        generate_with_salt(&schema, &state, "abc123"),
+        Err(InvalidSalt("abc123".to_string()))
+    );
+    assert_eq!(
+        generate_with_salt(&schema, &state, "ABC"),
+        Err(InvalidSalt("ABC".to_string()))
+    );
+}
+
+#[test]
+fn describe() {
+    let schema = crate::schema::compile_schema(
+        r#"schema "-" "_" [ category "Media" (exactly 1) ['art', 'photo'/'ph'], category "Place" (at_least 0) ['home'], category "People" (at_least 0) ['nate', 'kim'/'k']]"#,
+    )
+    .unwrap();
+    let mut state = crate::app::to_empty_state(&schema);
+    assert_eq!(describe_selection(&state), "");
+
+    state[0].1[1].1 = true;
+    state[2].1[0].1 = true;
+    state[2].1[1].1 = true;
+    assert_eq!(
+        describe_selection(&state),
+        "Media: photo; People: nate, kim"
+    );
+}
+
+#[test]
+fn generate_batch_collisions() {
+    let schema = crate::schema::compile_schema(crate::schema::TEST_SCHEMA).unwrap();
+    let mut state = crate::app::to_empty_state(&schema);
+    state[0].1[1].1 = true;
+    let mut other = state.clone();
+    other[1].1[0].1 = true;
+
+    let batch = vec![
+        ("ABC123".to_string(), state.clone()),
+        ("ABC123".to_string(), other),
+        ("ABC123".to_string(), state.clone()),
+        ("XYZ789".to_string(), state),
+    ];
+    assert_eq!(
+        generate_batch(&schema, &batch),
+        vec![
+            Err(Collision(vec![0, 2])),
+            Ok("ABC123-ph-nate".to_string()),
+            Err(Collision(vec![0, 2])),
+            Ok("XYZ789-ph-_".to_string()),
+        ]
+    );
+}
+
+#[test]
+fn generate_labeled() {
+    let mut schema = crate::schema::compile_schema(crate::schema::TEST_SCHEMA).unwrap();
+    schema.labeled = true;
+    let mut state = crate::app::to_empty_state(&schema);
+    state[0].1[1].1 = true;
+    assert_eq!(
+        generate_with_salt(&schema, &state, "ABC123"),
+        Ok("ABC123-Media.ph-People._".to_string())
+    );
+}
+
+#[test]
+fn unused() {
+    let schema = crate::schema::compile_schema(crate::schema::TEST_SCHEMA).unwrap();
+    assert_eq!(
+        unused_keywords(&schema, &["ABC123-ph-_", "ABC123-v-nate", "not a filename"]),
+        vec![("Media".to_string(), vec!["art".to_string()])]
+    );
+    assert_eq!(
+        unused_keywords(&schema, &[]),
+        vec![
+            (
+                "Media".to_string(),
+                vec!["art".to_string(), "ph".to_string(), "v".to_string()]
+            ),
+            ("People".to_string(), vec!["nate".to_string()]),
+        ]
+    );
+}
+
+#[test]
+fn same_selection_ignores_salt() {
+    let schema = crate::schema::compile_schema(crate::schema::TEST_SCHEMA).unwrap();
+    let a = schema.parse("ABC123-ph-nate").unwrap();
+    let b = schema.parse("XYZ789-ph-nate").unwrap();
+    assert!(same_selection(&a, &b));
+
+    let c = schema.parse("ABC123-ph-_").unwrap();
+    assert!(!same_selection(&a, &c));
+    let d = schema.parse("ABC123-v-nate").unwrap();
+    assert!(!same_selection(&a, &d));
+
+    // descriptions aren't part of the selection
+    let mut described = a.clone();
+    described[0].1[1].0.description = Some("Photographs".to_string());
+    assert!(same_selection(&a, &described));
+}
+
+#[test]
+fn generate_versioned() {
+    let mut schema = crate::schema::compile_schema(crate::schema::TEST_SCHEMA).unwrap();
+    let mut state = crate::app::to_empty_state(&schema);
+    state[0].1[1].1 = true;
+    assert_eq!(
+        generate_with_salt(&schema, &state, "ABC123"),
+        Ok("ABC123-ph-_".to_string())
+    );
+    schema.version = Some(2);
+    assert_eq!(
+        generate_with_salt(&schema, &state, "ABC123"),
+        Ok("ABC123-v2-ph-_".to_string())
+    );
+}
+
+#[test]
+fn generate_checksum_salt() {
+    let mut schema = crate::schema::compile_schema(crate::schema::TEST_SCHEMA).unwrap();
+    schema.salt.mode = SaltMode::Checksum;
+    let mut state = crate::app::to_empty_state(&schema);
+    state[0].1[1].1 = true;
+    let same = state.clone();
+    let mut other = state.clone();
+    other[1].1[0].1 = true;
+
+    let name = generate_checksummed(&schema, &state).unwrap();
+    assert_eq!(generate_checksummed(&schema, &same), Ok(name.clone()));
+    assert_ne!(generate_checksummed(&schema, &other), Ok(name.clone()));
+
+    let salt = &name[..schema.salt.len];
+    assert!(schema.salt.is_valid(salt));
+    assert_eq!(salt, schema.salt.checksum("ph-_"));
+    assert_eq!(
+        generate_with_salt(&schema, &state, "ABC123"),
+        Err(InvalidSalt("ABC123".to_string()))
+    );
+}
+
+#[test]
+fn generate_trailing_salt() {
+    let mut schema = crate::schema::compile_schema(crate::schema::TEST_SCHEMA).unwrap();
+    schema.salt.position = SaltPosition::Trailing;
+    let mut state = crate::app::to_empty_state(&schema);
+    state[0].1[1].1 = true;
+    assert_eq!(
+        generate_with_salt(&schema, &state, "ABC123"),
+        Ok("ph-_-ABC123".to_string())
+    );
+    schema.version = Some(2);
+    assert_eq!(
+        generate_with_salt(&schema, &state, "ABC123"),
+        Ok("v2-ph-_-ABC123".to_string())
+    );
+}
+
+#[test]
+fn generate_pairs() {
+    let schema = crate::schema::compile_schema(crate::schema::TEST_SCHEMA).unwrap();
+    assert_eq!(
+        generate_from_pairs(&schema, &[("People", "nate"), ("Media", "ph")], "ABC123"),
+        Ok("ABC123-ph-nate".to_string())
+    );
+    assert_eq!(
+        generate_from_pairs(&schema, &[("People", "art")], "ABC123"),
+        Err(NotInCategory {
+            category: "People".to_string(),
+            id: "art".to_string(),
+        })
+    );
+    assert_eq!(
+        generate_from_pairs(&schema, &[("Places", "home")], "ABC123"),
+        Err(NotInCategory {
+            category: "Places".to_string(),
+            id: "home".to_string(),
+        })
+    );
+    assert!(matches!(
+        generate_from_pairs(&schema, &[("People", "nate")], "ABC123"),
+        Err(RequirementMismatch { .. })
+    ));
+}
+
+#[test]
+fn distance() {
+    let schema = crate::schema::compile_schema(crate::schema::TEST_SCHEMA).unwrap();
+    let mut a = crate::app::to_empty_state(&schema);
+    a[0].1[0].1 = true;
+    let mut b = a.clone();
+    assert_eq!(selection_distance(&a, &b), 0);
+    b[1].1[0].1 = true;
+    assert_eq!(selection_distance(&a, &b), 1);
+
+    // swapping a tag within a category is one difference
+    let mut swapped = a.clone();
+    swapped[0].1[0].1 = false;
+    swapped[0].1[2].1 = true;
+    assert_eq!(selection_distance(&a, &swapped), 1);
+
+    // every category differs
+    b[0].1[0].1 = false;
+    b[0].1[2].1 = true;
+    assert_eq!(selection_distance(&a, &b), 2);
+    assert_eq!(selection_distance(&b, &a), 2);
+}
+
+#[test]
+fn generate_salt_except() {
+    use rand::rngs::mock::StepRng;
+
+    let spec = SaltSpec {
+        len: 1,
+        charset: "ab",
+        ..SaltSpec::default()
+    };
+    // alternates between the first and second characters of the charset
+    let mut rng = StepRng::new(0, 1 << 63);
+    assert_eq!(spec.generate(&mut rng), "a");
+    let mut rng = StepRng::new(0, 1 << 63);
+    assert_eq!(spec.generate_except(&mut rng, "a"), Some("b".to_string()));
+
+    let spec = SaltSpec {
+        charset: "a",
+        ..spec
+    };
+    assert_eq!(spec.generate_except(&mut rng, "a"), None);
+    assert_eq!(spec.generate_except(&mut rng, "_"), Some("a".to_string()));
+}
+
+#[test]
+fn salt_collision_probability() {
+    let spec = SaltSpec {
+        len: 2,
+        charset: "AB",
+        ..SaltSpec::default()
+    };
+    // 4 possible salts and 3 pairs: 1 - e^(-3/4)
+    assert!((spec.collision_probability(3) - 0.527_633_4).abs() < 1e-6);
+    assert_eq!(spec.collision_probability(0), 0.0);
+    assert_eq!(spec.collision_probability(1), 0.0);
+    let default = SaltSpec::default();
+    assert!(default.collision_probability(1000) < 0.001);
+    assert!(default.collision_probability(1000) > 0.0);
+}
+
+#[test]
+fn generate_draft() {
+    let schema = crate::schema::compile_schema(
+        r#"schema "-" "_" [ category "Media" (exactly 1) ['art', draft 'photo'/'ph'] ]"#,
+    )
+    .unwrap();
+    assert!(schema.categories[0].1[1].draft);
+    let state = schema.parse("ABC123-ph").unwrap();
+    assert_eq!(schema.is_canonical("ABC123-ph"), Ok(true));
+    let draft = DraftKeyword {
+        category: "Media".to_string(),
+        id: "ph".to_string(),
+    };
+    assert_eq!(generate(&schema, &state), Err(draft.clone()));
+    assert_eq!(generate_with_salt(&schema, &state, "ABC123"), Err(draft));
+    assert_eq!(
+        generate(&schema, &schema.minimal_selection()),
+        Ok("art".to_string())
+    );
+    assert_eq!(schema.completions("Media", ""), vec!["art"]);
+
+    let expr = crate::schema::schema_to_expr(&schema);
+    assert_eq!(
+        crate::schema::typecheck::typecheck(expr.clone()),
+        Ok(schema)
+    );
+    assert_eq!(
+        crate::schema::parse::parse(&crate::schema::parse::unparse(&expr)),
+        Ok(expr)
+    );
+}
@@ -0,0 +1,1648 @@
+use super::{
+    EmptyMode,
+    FilenameParseError::{self, *},
+    SaltMode, SaltPosition, LABEL_DELIM,
+};
+use crate::{
+    app::to_empty_state,
+    schema::{Category, Keyword, Schema},
+    State,
+};
+use std::{
+    borrow::Cow,
+    collections::{HashMap, HashSet},
+    result::Result as StdResult,
+};
+
+pub type Result<T> = StdResult<T, FilenameParseError>;
+
+/// opt-in relaxations of the filename format accepted by `Schema::parse_with`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ParseOptions {
+    /// assign each tag to whichever category contains it regardless of the order categories appear in.
+    pub any_order: bool,
+    /// ignore whitespace around each segment, as in hand typed names like `salt - art - ph`.
+    pub trim_whitespace: bool,
+    /// accept salts in either case, as from filesystems that fold case. Tags are still case sensitive.
+    pub salt_ignore_case: bool,
+}
+
+/// the tags of one category that differ between two filenames.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TagChange {
+    pub category: String,
+    /// tags only in the second filename
+    pub added: Vec<String>,
+    /// tags only in the first filename
+    pub removed: Vec<String>,
+}
+
+/// how far a partially typed filename gets through the schema.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PartialParse {
+    /// names of the categories that can't take any more tags
+    pub complete: Vec<String>,
+    /// name of the category the next tag will be added to
+    pub in_progress: Option<String>,
+    /// tags that could validly come next, filtered by any partially typed tag
+    pub next: Vec<String>,
+}
+
+impl Schema {
+    /// parses a filename without its extension back into the selection that generated it.
+    /// Keywords are looked up by scanning the schema, so use `PreparedSchema` when parsing
+    /// many filenames against a large schema.
+    pub fn parse(&self, filename: &str) -> Result<State> {
+        self.parse_with(filename, &ParseOptions::default())
+    }
+
+    pub fn parse_with(&self, filename: &str, opts: &ParseOptions) -> Result<State> {
+        let mut state = to_empty_state(self);
+        self.parse_into_with(filename, &mut state, opts)?;
+        Ok(state)
+    }
+
+    /// parses a filename into an existing selection, reusing its allocations when
+    /// it was built from this schema. On error the buffer's contents are unspecified.
+    pub fn parse_into(&self, filename: &str, buf: &mut State) -> Result<()> {
+        self.parse_into_with(filename, buf, &ParseOptions::default())
+    }
+
+    pub fn parse_into_with(
+        &self,
+        filename: &str,
+        buf: &mut State,
+        opts: &ParseOptions,
+    ) -> Result<()> {
+        PreparedSchema::unindexed(self).parse_into_with(filename, buf, opts)
+    }
+
+    /// parses a filename into each category's name along with the ids of its tags in filename order.
+    pub fn parse_tags(&self, filename: &str) -> Result<Vec<(String, Vec<String>)>> {
+        Ok(self
+            .parse(filename)?
+            .into_iter()
+            .map(|(cat, kws)| {
+                let ids = kws
+                    .into_iter()
+                    .filter(|(_, tf)| *tf)
+                    .map(|(kw, _)| kw.id)
+                    .collect();
+                (cat.name, ids)
+            })
+            .collect())
+    }
+
+    /// the tags added and removed in each category going from filename `a` to `b`.
+    /// Categories with the same tags in both are left out, and salts are ignored.
+    pub fn diff_filenames(&self, a: &str, b: &str) -> Result<Vec<TagChange>> {
+        let (a, b) = (self.parse_tags(a)?, self.parse_tags(b)?);
+        Ok(a.into_iter()
+            .zip(b)
+            .filter_map(|((category, a), (_, b))| {
+                let added: Vec<String> = b.iter().filter(|id| !a.contains(id)).cloned().collect();
+                let removed: Vec<String> = a.iter().filter(|id| !b.contains(id)).cloned().collect();
+                (!added.is_empty() || !removed.is_empty()).then_some(TagChange {
+                    category,
+                    added,
+                    removed,
+                })
+            })
+            .collect())
+    }
+
+    /// parses each filename on rayon's thread pool, with results in the same order as the filenames.
+    #[cfg(feature = "rayon")]
+    pub fn parse_par(&self, filenames: &[String]) -> Vec<Result<State>> {
+        use rayon::prelude::*;
+
+        let prepared = PreparedSchema::new(self);
+        filenames
+            .par_iter()
+            .map(|filename| prepared.parse(filename))
+            .collect()
+    }
+
+    /// deselects every tag, rebuilding the state if it doesn't match this schema.
+    fn reset_state(&self, state: &mut State) {
+        let same_shape = state.len() == self.categories.len()
+            && state
+                .iter()
+                .zip(&self.categories)
+                .all(|((a, akws), (b, bkws))| a == b && akws.len() == bkws.len());
+        if same_shape {
+            for (_, kws) in state.iter_mut() {
+                for (_, tf) in kws.iter_mut() {
+                    *tf = false;
+                }
+            }
+        } else {
+            *state = to_empty_state(self);
+        }
+    }
+
+    /// parses the filename while skipping over any tags the schema doesn't know about.
+    /// returns the selection if the known tags form a valid filename, along with every unknown tag.
+    pub fn parse_collecting(&self, filename: &str) -> (Option<State>, Vec<String>) {
+        let Ok((_, rest)) = self.split_header(filename) else {
+            return (None, vec![]);
+        };
+        let segments = self.segments(rest);
+        let (known, unknown): (Vec<&str>, Vec<&str>) = segments
+            .iter()
+            .map(AsRef::as_ref)
+            .partition(|seg| self.is_known_tag(seg));
+        let mut state = to_empty_state(self);
+        let state = PreparedSchema::unindexed(self)
+            .parse_tags(known.into_iter(), &mut state, &ParseOptions::default())
+            .map(|_| state)
+            .ok();
+        (state, unknown.into_iter().map(str::to_string).collect())
+    }
+
+    fn is_known_tag(&self, seg: &str) -> bool {
+        let (name, tag) = match seg.split_once(LABEL_DELIM) {
+            Some((name, tag)) if self.labeled => (Some(name), tag),
+            _ if self.labeled => return false,
+            _ => (None, seg),
+        };
+        self.categories
+            .iter()
+            .filter(|(cat, _)| name.is_none_or(|name| name == cat.name))
+            .any(|(cat, kws)| {
+                tag == self.empty || cat.freeform || kws.iter().any(|kw| kw.id == tag)
+            })
+    }
+
+    /// whether the tag is one of the keywords of a category that isn't freeform.
+    fn is_keyword(&self, tag: &str) -> bool {
+        self.categories
+            .iter()
+            .any(|(_, kws)| kws.iter().any(|kw| kw.id == tag))
+    }
+
+    /// whether a freeform category can take this tag rather than it being a keyword of some category.
+    fn takes_freeform(&self, kws: &[(Keyword, bool)], tag: &str) -> bool {
+        !tag.is_empty()
+            && tag != self.empty
+            && !self.is_keyword(tag)
+            && !kws.iter().any(|(kw, _)| kw.id == tag)
+    }
+
+    /// parses as much of a partially typed filename as possible to find out what could come next.
+    pub fn validate_partial(&self, prefix: &str) -> Result<PartialParse> {
+        // the salt is still being typed
+        if self.salt.position == SaltPosition::Leading && !self.delim.is_in(prefix) {
+            let typed = prefix.chars().count();
+            return if typed <= self.salt.len
+                && prefix.chars().all(|c| self.salt.charset.contains(c))
+            {
+                Ok(PartialParse::default())
+            } else {
+                Err(InvalidSalt(prefix.to_string()))
+            };
+        }
+
+        // a trailing salt is typed after every tag
+        let rest = match self.salt.position {
+            SaltPosition::Leading => self.split_salt(prefix)?.1,
+            SaltPosition::Trailing => prefix,
+        };
+        let rest = match self.version_segment() {
+            // the version is still being typed
+            Some(version) if !self.delim.is_in(rest) => {
+                return if version.starts_with(rest) {
+                    Ok(PartialParse::default())
+                } else {
+                    Err(VersionMismatch {
+                        expected: self.version.unwrap_or_default(),
+                        got: rest.to_string(),
+                    })
+                };
+            }
+            _ => self.split_version(rest)?,
+        };
+        let owned = self.segments(rest);
+        let mut segments: Vec<&str> = owned.iter().map(AsRef::as_ref).collect();
+        // the last segment is always the one currently being typed, even if it's empty
+        let typing = segments.pop().unwrap_or("");
+
+        let mut state = to_empty_state(self);
+        let (mut i, mut count) = (0, 0);
+        for seg in segments {
+            loop {
+                let Some((cat, kws)) = state.get_mut(i) else {
+                    return Err(UnexpectedTag(seg.to_string()));
+                };
+                if count == 0 && seg == self.empty && cat.requirement.satisfied_by(0) {
+                    (i, count) = (i + 1, 0);
+                    break;
+                }
+                let full = cat.requirement.max_count().is_some_and(|max| count >= max);
+                if let Some((_, tf)) = kws.iter_mut().find(|(kw, tf)| !*tf && kw.id == seg) {
+                    if !full {
+                        *tf = true;
+                        count += 1;
+                        break;
+                    }
+                } else if cat.freeform && !full && self.takes_freeform(kws, seg) {
+                    kws.push((freeform_keyword(seg), true));
+                    count += 1;
+                    break;
+                }
+                // the tag might start the next category
+                if count > 0 && cat.requirement.satisfied_by(count) {
+                    (i, count) = (i + 1, 0);
+                    continue;
+                }
+                return Err(UnexpectedTag(seg.to_string()));
+            }
+            if let Some(max) = state
+                .get(i)
+                .and_then(|(cat, _)| cat.requirement.max_count())
+            {
+                if count > 0 && count >= max {
+                    (i, count) = (i + 1, 0);
+                }
+            }
+        }
+
+        let mut next = vec![];
+        if let Some((cat, kws)) = state.get(i) {
+            if count == 0 && cat.requirement.satisfied_by(0) {
+                next.push(self.empty.clone());
+            }
+            next.extend(
+                kws.iter()
+                    .filter(|(_, tf)| !tf)
+                    .map(|(kw, _)| kw.id.clone()),
+            );
+            if count > 0 && cat.requirement.satisfied_by(count) {
+                if let Some((cat, kws)) = state.get(i + 1) {
+                    if cat.requirement.satisfied_by(0) {
+                        next.push(self.empty.clone());
+                    }
+                    next.extend(kws.iter().map(|(kw, _)| kw.id.clone()));
+                }
+            }
+        }
+        // keep the first of each tag so they stay in schema order
+        let mut seen = HashSet::new();
+        next.retain(|tag| tag.starts_with(typing) && seen.insert(tag.clone()));
+
+        Ok(PartialParse {
+            complete: state[..i].iter().map(|(cat, _)| cat.name.clone()).collect(),
+            in_progress: state.get(i).map(|(cat, _)| cat.name.clone()),
+            next,
+        })
+    }
+
+    /// proposes corrections for a filename by replacing every tag the schema doesn't
+    /// know about with its closest tag by edit distance. Only suggestions that parse are kept.
+    pub fn suggest(&self, filename: &str) -> Vec<String> {
+        let Ok((_, tagged)) = self.split_salt(filename) else {
+            return vec![];
+        };
+        let Ok(rest) = self.split_version(tagged) else {
+            return vec![];
+        };
+        if rest.is_empty() || self.segments(rest).iter().all(|seg| self.is_known_tag(seg)) {
+            return vec![];
+        }
+
+        let mut candidates: Vec<String> = self
+            .categories
+            .iter()
+            .flat_map(|(cat, kws)| {
+                std::iter::once(&self.empty)
+                    .chain(kws.iter().map(|kw| &kw.id))
+                    .map(move |tag| {
+                        if self.labeled {
+                            format!("{}{LABEL_DELIM}{tag}", cat.name)
+                        } else {
+                            tag.clone()
+                        }
+                    })
+            })
+            .collect();
+        candidates.sort();
+        candidates.dedup();
+
+        // the salt and version around the tags, including their delimiters. The version
+        // leads the tags, and the salt is on whichever side of them the schema puts it.
+        let salt_len = match self.salt.position {
+            SaltPosition::Leading => filename.len() - tagged.len(),
+            SaltPosition::Trailing => 0,
+        };
+        let start = salt_len + tagged.len() - rest.len();
+        let (before, after) = (&filename[..start], &filename[start + rest.len()..]);
+        let mut suggestions = vec![before.to_string()];
+        for (i, seg) in self.segments(rest).iter().enumerate() {
+            let options: Vec<&str> = if self.is_known_tag(seg) {
+                vec![seg.as_ref()]
+            } else {
+                let distances: Vec<usize> =
+                    candidates.iter().map(|c| levenshtein(seg, c)).collect();
+                let best = distances.iter().min().copied().unwrap_or(0);
+                candidates
+                    .iter()
+                    .zip(distances)
+                    .filter(|(_, d)| *d == best)
+                    .map(|(c, _)| c.as_str())
+                    .collect()
+            };
+            suggestions = suggestions
+                .iter()
+                .flat_map(|prefix| {
+                    options.iter().map(move |tag| match i {
+                        0 => format!("{prefix}{}", self.escape_tag(tag)),
+                        _ => format!("{prefix}{}{}", self.delim, self.escape_tag(tag)),
+                    })
+                })
+                .collect();
+        }
+        for name in suggestions.iter_mut() {
+            name.push_str(after);
+        }
+        suggestions.retain(|name| self.parse(name).is_ok());
+        suggestions
+    }
+
+    /// whether the filename is exactly what generating its own selection would produce,
+    /// with the same tag order and ids. Errors if tags in any order don't form a valid filename.
+    pub fn is_canonical(&self, filename: &str) -> Result<bool> {
+        let state = self.parse_with(
+            filename,
+            &ParseOptions {
+                any_order: true,
+                ..ParseOptions::default()
+            },
+        )?;
+        let (salt, _) = self.split_header(filename)?;
+        Ok(super::write_with_salt(self, &state, salt).is_ok_and(|name| name == filename))
+    }
+
+    /// the byte offset where the filename first differs from what generating its own
+    /// selection would produce, with a description of the difference. `None` when it's canonical.
+    pub fn first_diff(&self, filename: &str) -> Result<Option<(usize, String)>> {
+        let state = self.parse_with(
+            filename,
+            &ParseOptions {
+                any_order: true,
+                ..ParseOptions::default()
+            },
+        )?;
+        let (salt, _) = self.split_header(filename)?;
+        let canonical = super::write_with_salt(self, &state, salt)?;
+        let Some(at) = filename
+            .char_indices()
+            .zip(canonical.chars())
+            .find(|((_, a), b)| a != b)
+            .map(|((i, _), _)| i)
+            .or_else(|| {
+                (filename.len() != canonical.len()).then(|| filename.len().min(canonical.len()))
+            })
+        else {
+            return Ok(None);
+        };
+
+        // the whole delimited segment around the offset
+        let d = self.delim.to_string();
+        let segment_at = |name: &'_ str| -> String {
+            let at = at.min(name.len());
+            let start = name[..at].rfind(&d).map_or(0, |i| i + d.len()).min(at);
+            let end = name[at..].find(&d).map_or(name.len(), |i| at + i);
+            name[start..end].to_string()
+        };
+        let (found, expected) = (segment_at(filename), segment_at(&canonical));
+        let is_tag = |seg: &str| !seg.is_empty() && seg != self.empty;
+        let description = if is_tag(&found) && is_tag(&expected) {
+            format!("tags out of order at offset {at}: expected \"{expected}\", found \"{found}\"")
+        } else {
+            format!("expected \"{expected}\", found \"{found}\" at offset {at}")
+        };
+        Ok(Some((at, description)))
+    }
+
+    /// parses the longest run of leading segments that forms a valid filename, returning
+    /// whatever follows it, such as a note appended by hand. The tail is empty when the whole
+    /// filename parses. Errors as parsing the whole filename would when no prefix parses.
+    pub fn parse_prefix<'f>(&self, filename: &'f str) -> Result<(State, &'f str)> {
+        let prepared = PreparedSchema::new(self);
+        let d = self.delim.to_string();
+        let mut ends: Vec<usize> = filename.match_indices(&d).map(|(i, _)| i).collect();
+        ends.push(filename.len());
+        let mut first_err = None;
+        for end in ends.into_iter().rev() {
+            match prepared.parse(&filename[..end]) {
+                Ok(state) => {
+                    let tail = filename[end..].strip_prefix(&d).unwrap_or_default();
+                    return Ok((state, tail));
+                }
+                Err(e) => {
+                    first_err.get_or_insert(e);
+                }
+            }
+        }
+        Err(first_err.expect("the whole filename is always tried"))
+    }
+
+    /// validates and returns only the salt of a filename.
+    pub fn extract_salt(&self, filename: &str) -> Result<String> {
+        self.split_salt(filename).map(|(salt, _)| salt.to_string())
+    }
+
+    /// splits the tags apart on the delimiter, and then on any category's intra-category delimiter.
+    /// Tags are only owned when they had an escaped delimiter in them.
+    fn segments<'a>(&'a self, rest: &'a str) -> Vec<Cow<'a, str>> {
+        let mut segments: Vec<Cow<str>> = if self.escape_delim {
+            self.delim.split_escaped(rest)
+        } else {
+            self.delim.split(rest).map(Cow::Borrowed).collect()
+        };
+        for intra in self
+            .categories
+            .iter()
+            .filter_map(|(cat, _)| cat.intra_delim.as_ref())
+        {
+            segments = segments
+                .into_iter()
+                .flat_map(|seg| match seg {
+                    Cow::Borrowed(seg) => intra.split(seg).map(Cow::Borrowed).collect::<Vec<_>>(),
+                    Cow::Owned(seg) => intra
+                        .split(&seg)
+                        .map(|s| Cow::Owned(s.to_string()))
+                        .collect(),
+                })
+                .collect();
+        }
+        segments
+    }
+
+    /// splits off the salt and, when the schema has one, the version segment that follows it.
+    fn split_header<'a>(&self, filename: &'a str) -> Result<(&'a str, &'a str)> {
+        let (salt, rest) = self.split_salt(filename)?;
+        Ok((salt, self.split_version(rest)?))
+    }
+
+    /// checks and removes the version segment from the start of the tags.
+    fn split_version<'a>(&self, rest: &'a str) -> Result<&'a str> {
+        let (Some(expected), Some(version)) = (self.version, self.version_segment()) else {
+            return Ok(rest);
+        };
+        let (seg, tags) = self.delim.split_once(rest).unwrap_or((rest, ""));
+        if seg.is_empty() {
+            Err(UnexpectedEnd)
+        } else if seg != version {
+            Err(VersionMismatch {
+                expected,
+                got: seg.to_string(),
+            })
+        } else {
+            Ok(tags)
+        }
+    }
+
+    /// validates the salt and splits it from everything on the other side of its delimiter.
+    fn split_salt<'a>(&self, filename: &'a str) -> Result<(&'a str, &'a str)> {
+        if filename.is_empty() {
+            return Err(MissingSalt);
+        }
+        let (salt, rest) = match self.salt.position {
+            SaltPosition::Leading => self.delim.split_once(filename),
+            SaltPosition::Trailing => self
+                .delim
+                .rsplit_once(filename)
+                .map(|(rest, salt)| (salt, rest)),
+        }
+        .unwrap_or((filename, ""));
+        if self.salt.is_valid(salt) {
+            return Ok((salt, rest));
+        }
+
+        // a salt that contains the delimiter gets split early, so check the
+        // full expected width of the salt before reporting it as invalid.
+        match self.salt.position {
+            SaltPosition::Leading => {
+                let width = filename
+                    .char_indices()
+                    .nth(self.salt.len)
+                    .map_or(filename.len(), |(i, _)| i);
+                let (salt, rest) = filename.split_at(width);
+                if self.delim.is_in(salt) && self.delim.starts(rest) {
+                    return Err(DelimiterInSalt(salt.to_string()));
+                }
+                Err(InvalidSalt(salt.to_string()))
+            }
+            SaltPosition::Trailing => {
+                let start = self
+                    .salt
+                    .len
+                    .checked_sub(1)
+                    .and_then(|n| filename.char_indices().rev().nth(n))
+                    .map_or(0, |(i, _)| i);
+                let (rest, salt) = filename.split_at(start);
+                if self.delim.is_in(salt) && self.delim.ends(rest) {
+                    return Err(DelimiterInSalt(salt.to_string()));
+                }
+                Err(InvalidSalt(salt.to_string()))
+            }
+        }
+    }
+}
+
+/// a schema with the lookups that parsing needs built ahead of time, for
+/// parsing many filenames against the same schema.
+#[derive(Clone, Debug)]
+pub struct PreparedSchema<'s> {
+    schema: &'s Schema,
+    /// every (category, keyword) position holding each keyword id, in schema order.
+    /// Without it keywords are found by scanning the schema, which is quicker for one filename.
+    index: Option<HashMap<&'s str, Vec<(usize, usize)>>>,
+}
+
+impl<'s> PreparedSchema<'s> {
+    pub fn new(schema: &'s Schema) -> Self {
+        let mut index: HashMap<&str, Vec<(usize, usize)>> = HashMap::new();
+        for (c, (_, kws)) in schema.categories.iter().enumerate() {
+            for (k, kw) in kws.iter().enumerate() {
+                index.entry(kw.id.as_str()).or_default().push((c, k));
+            }
+        }
+        PreparedSchema {
+            schema,
+            index: Some(index),
+        }
+    }
+
+    /// parses the way `PreparedSchema::new` does without building anything first.
+    fn unindexed(schema: &'s Schema) -> Self {
+        PreparedSchema {
+            schema,
+            index: None,
+        }
+    }
+
+    pub fn schema(&self) -> &'s Schema {
+        self.schema
+    }
+
+    /// same as `Schema::parse`.
+    pub fn parse(&self, filename: &str) -> Result<State> {
+        self.parse_with(filename, &ParseOptions::default())
+    }
+
+    pub fn parse_with(&self, filename: &str, opts: &ParseOptions) -> Result<State> {
+        let mut state = to_empty_state(self.schema);
+        self.parse_into_with(filename, &mut state, opts)?;
+        Ok(state)
+    }
+
+    /// same as `Schema::parse_into`.
+    pub fn parse_into(&self, filename: &str, buf: &mut State) -> Result<()> {
+        self.parse_into_with(filename, buf, &ParseOptions::default())
+    }
+
+    pub fn parse_into_with(
+        &self,
+        filename: &str,
+        buf: &mut State,
+        opts: &ParseOptions,
+    ) -> Result<()> {
+        let schema = self.schema;
+        let mut filename = Cow::Borrowed(filename);
+        if opts.trim_whitespace {
+            filename = Cow::Owned(
+                schema
+                    .delim
+                    .split(&filename)
+                    .map(str::trim)
+                    .collect::<Vec<_>>()
+                    .join(&schema.delim.to_string()),
+            );
+        }
+        if opts.salt_ignore_case {
+            // without a delimiter the whole name is the salt
+            let (start, end) = match schema.salt.position {
+                SaltPosition::Leading => (
+                    0,
+                    schema
+                        .delim
+                        .split_once(&filename)
+                        .map_or(filename.len(), |(salt, _)| salt.len()),
+                ),
+                SaltPosition::Trailing => (
+                    schema
+                        .delim
+                        .rsplit_once(&filename)
+                        .map_or(0, |(_, salt)| filename.len() - salt.len()),
+                    filename.len(),
+                ),
+            };
+            let matched = schema.salt.match_case(&filename[start..end]);
+            filename = Cow::Owned(format!(
+                "{}{matched}{}",
+                &filename[..start],
+                &filename[end..]
+            ));
+        }
+        let filename = filename.as_ref();
+        let (salt, rest) = schema.split_header(filename)?;
+        schema.reset_state(buf);
+        let segments = schema.segments(rest);
+        self.parse_tags(segments.iter().map(AsRef::as_ref), buf, opts)?;
+        if schema.salt.mode == SaltMode::Checksum {
+            // checksums are over the tags in the order they're generated
+            let tags = super::write_tags(schema, buf)?;
+            if salt != schema.salt.checksum(&tags) {
+                return Err(ChecksumMismatch {
+                    expected: tags,
+                    got: salt.to_string(),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// first (category, keyword) position of the keyword id accepted by the filter.
+    fn locate(
+        &self,
+        id: &str,
+        mut accept: impl FnMut(usize, usize) -> bool,
+    ) -> Option<(usize, usize)> {
+        match &self.index {
+            Some(index) => index.get(id)?.iter().copied().find(|(c, k)| accept(*c, *k)),
+            None => self
+                .schema
+                .categories
+                .iter()
+                .enumerate()
+                .flat_map(|(c, (_, kws))| kws.iter().enumerate().map(move |(k, kw)| (c, k, kw)))
+                .find(|(c, k, kw)| kw.id == id && accept(*c, *k))
+                .map(|(c, k, _)| (c, k)),
+        }
+    }
+
+    /// keyword position of `locate`.
+    fn find(&self, id: &str, accept: impl FnMut(usize, usize) -> bool) -> Option<usize> {
+        self.locate(id, accept).map(|(_, k)| k)
+    }
+
+    /// whether a freeform category can take this tag rather than it being a keyword of some category.
+    fn takes_freeform(&self, kws: &[(Keyword, bool)], tag: &str) -> bool {
+        !tag.is_empty()
+            && tag != self.schema.empty
+            && self.find(tag, |_, _| true).is_none()
+            && !kws.iter().any(|(kw, _)| kw.id == tag)
+    }
+
+    /// parses the segments after the salt into a state with no tags selected.
+    fn parse_tags<'a>(
+        &self,
+        segments: impl Iterator<Item = &'a str>,
+        state: &mut State,
+        opts: &ParseOptions,
+    ) -> Result<()> {
+        if self.schema.labeled {
+            self.parse_labeled(segments, state)?;
+        } else if opts.any_order {
+            self.parse_any_order(segments, state)?;
+        } else {
+            self.parse_in_order(segments, state)?;
+        }
+        self.check_requirements(state)
+    }
+
+    fn parse_in_order<'a>(
+        &self,
+        segments: impl Iterator<Item = &'a str>,
+        state: &mut State,
+    ) -> Result<()> {
+        let collapse = self.schema.empty_mode == EmptyMode::Collapse;
+        let mut segments = segments.peekable();
+        for i in 0..state.len() {
+            match segments.peek() {
+                // collapsed categories at the end leave nothing behind
+                None if collapse => (),
+                None => return Err(UnexpectedEnd),
+                Some(seg) if *seg == self.schema.empty => {
+                    segments.next();
+                }
+                Some(seg) => {
+                    let first = *seg;
+                    let (earlier, rest) = state.split_at_mut(i);
+                    let (cat, kws) = &mut rest[0];
+                    // consume tags for as long as they belong to this category
+                    let mut found = 0;
+                    while let Some(seg) = segments.peek() {
+                        if let Some(k) = self.find(seg, |c, k| c == i && !kws[k].1) {
+                            kws[k].1 = true;
+                        } else if cat.freeform && self.takes_freeform(kws, seg) {
+                            kws.push((freeform_keyword(seg), true));
+                        } else if !self.repeat(earlier, seg) {
+                            break;
+                        }
+                        segments.next();
+                        found += 1;
+                    }
+                    // a category is never skipped without the empty placeholder unless it was collapsed
+                    if found == 0 && !collapse {
+                        return Err(UnexpectedTag(first.to_string()));
+                    }
+                }
+            }
+        }
+
+        for seg in segments {
+            if !self.repeat(state, seg) {
+                return Err(UnexpectedTag(seg.to_string()));
+            }
+        }
+        Ok(())
+    }
+
+    /// gives the tag to the first of these categories that's repeatable and can take it.
+    fn repeat(&self, categories: &mut [(Category, Vec<(Keyword, bool)>)], tag: &str) -> bool {
+        for (c, (cat, kws)) in categories.iter_mut().enumerate() {
+            if !cat.repeatable {
+                continue;
+            }
+            if let Some(k) = self.find(tag, |i, k| i == c && !kws[k].1) {
+                kws[k].1 = true;
+                return true;
+            }
+            if cat.freeform && self.takes_freeform(kws, tag) {
+                kws.push((freeform_keyword(tag), true));
+                return true;
+            }
+        }
+        false
+    }
+
+    fn parse_any_order<'a>(
+        &self,
+        segments: impl Iterator<Item = &'a str>,
+        state: &mut State,
+    ) -> Result<()> {
+        // without a fixed order, the empty placeholder carries no information
+        for seg in segments.filter(|seg| *seg != self.schema.empty) {
+            if let Some((c, k)) = self.locate(seg, |c, k| !state[c].1[k].1) {
+                state[c].1[k].1 = true;
+                continue;
+            }
+            // otherwise unknown tags belong to the first freeform category
+            let (_, kws) = state
+                .iter_mut()
+                .find(|(cat, kws)| cat.freeform && self.takes_freeform(kws, seg))
+                .ok_or_else(|| UnexpectedTag(seg.to_string()))?;
+            kws.push((freeform_keyword(seg), true));
+        }
+        Ok(())
+    }
+
+    /// labeled tags name their category so they can appear in any order.
+    fn parse_labeled<'a>(
+        &self,
+        segments: impl Iterator<Item = &'a str>,
+        state: &mut State,
+    ) -> Result<()> {
+        for seg in segments {
+            let (name, tag) = seg
+                .split_once(LABEL_DELIM)
+                .ok_or_else(|| UnexpectedTag(seg.to_string()))?;
+            let i = state
+                .iter()
+                .position(|(cat, _)| cat.name == name)
+                .ok_or_else(|| UnexpectedTag(seg.to_string()))?;
+            let (cat, kws) = &mut state[i];
+            if tag == self.schema.empty {
+                continue;
+            }
+            if cat.freeform && self.takes_freeform(kws, tag) {
+                kws.push((freeform_keyword(tag), true));
+                continue;
+            }
+            let k = self
+                .find(tag, |c, k| c == i && !kws[k].1)
+                .ok_or_else(|| UnexpectedTag(seg.to_string()))?;
+            kws[k].1 = true;
+        }
+        Ok(())
+    }
+
+    fn check_requirements(&self, state: &State) -> Result<()> {
+        let mut any = false;
+        for (cat, kws) in state {
+            let got = kws.iter().filter(|(_, tf)| *tf).count();
+            if !cat.requirement.canonical().satisfied_by(got) {
+                return Err(RequirementMismatch {
+                    category: cat.clone(),
+                    expected: cat.requirement,
+                    got,
+                });
+            }
+            any |= got > 0;
+        }
+        if self.schema.require_any_tag && !any {
+            return Err(NoTags);
+        }
+        Ok(())
+    }
+}
+
+/// freeform tags are added to the state as keywords named after themselves.
+fn freeform_keyword(tag: &str) -> Keyword {
+    Keyword {
+        name: tag.to_string(),
+        id: tag.to_string(),
+        description: None,
+        draft: false,
+    }
+}
+
+/// number of single character insertions, deletions, and substitutions to turn one string into the other.
+fn levenshtein(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut row = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let sub = prev[j] + usize::from(ca != *cb);
+            row.push(sub.min(prev[j + 1] + 1).min(row[j] + 1));
+        }
+        prev = row;
+    }
+    prev[b.len()]
+}
+
+#[cfg(test)]
+fn test_schema() -> Schema {
+    crate::schema::compile_schema(crate::schema::TEST_SCHEMA).unwrap()
+}
+
+#[test]
+fn parse_filename() {
+    let schema = test_schema();
+    let mut expected = to_empty_state(&schema);
+    expected[0].1[1].1 = true;
+    expected[1].1[0].1 = true;
+    assert_eq!(schema.parse("ABC123-ph-nate"), Ok(expected.clone()));
+
+    expected[1].1[0].1 = false;
+    assert_eq!(schema.parse("ABC123-ph-_"), Ok(expected));
+
+    assert_eq!(schema.parse(""), Err(MissingSalt));
+    assert_eq!(schema.parse("ABC123-ph"), Err(UnexpectedEnd));
+    assert_eq!(
+        schema.parse("ABC123-ph-boop"),
+        Err(UnexpectedTag("boop".to_string()))
+    );
+    assert_eq!(
+        schema.parse("ABC123-ph-_-nate"),
+        Err(UnexpectedTag("nate".to_string()))
+    );
+    assert!(matches!(
+        schema.parse("ABC123-ph-v-_"),
+        Err(RequirementMismatch { got: 2, .. })
+    ));
+}
+
+#[test]
+fn parse_delim_widths() {
+    let mut schema = test_schema();
+    assert_eq!(schema.delim, crate::schema::Delim::Char('-'));
+    let mut expected = to_empty_state(&schema);
+    expected[0].1[1].1 = true;
+    expected[1].1[0].1 = true;
+    assert_eq!(schema.parse("ABC123-ph-nate"), Ok(expected.clone()));
+
+    schema.delim = crate::schema::Delim::from("--");
+    assert_eq!(schema.delim, crate::schema::Delim::Str("--".to_string()));
+    assert_eq!(schema.parse("ABC123--ph--nate"), Ok(expected.clone()));
+    assert_eq!(
+        crate::filename::generate_with_salt(&schema, &expected, "ABC123"),
+        Ok("ABC123--ph--nate".to_string())
+    );
+    assert_eq!(
+        schema.parse("ABC123-ph-nate"),
+        Err(InvalidSalt("ABC123".to_string()))
+    );
+}
+
+#[test]
+fn parse_delimiter_in_salt() {
+    let mut schema = test_schema();
+    schema.salt.charset = "ABC-";
+    assert_eq!(
+        schema.parse("AB-CAB-ph-_"),
+        Err(DelimiterInSalt("AB-CAB".to_string()))
+    );
+    assert_eq!(
+        schema.parse("AB-CA-ph-_"),
+        Err(InvalidSalt("AB-CA-".to_string()))
+    );
+}
+
+#[test]
+fn extract_salt() {
+    let schema = test_schema();
+    assert_eq!(schema.extract_salt("ABC123-ph-_"), Ok("ABC123".to_string()));
+    // the tags are not checked
+    assert_eq!(schema.extract_salt("ABC123-boop"), Ok("ABC123".to_string()));
+    assert_eq!(schema.extract_salt("ABC123"), Ok("ABC123".to_string()));
+    assert_eq!(schema.extract_salt(""), Err(MissingSalt));
+    assert_eq!(
+        schema.extract_salt("ph-_"),
+        Err(InvalidSalt("ph-_".to_string()))
+    );
+}
+
+#[test]
+fn parse_any_order() {
+    let schema = test_schema();
+    let any_order = ParseOptions {
+        any_order: true,
+        ..ParseOptions::default()
+    };
+    let expected = schema.parse("ABC123-ph-nate");
+    assert!(expected.is_ok());
+    assert_eq!(schema.parse_with("ABC123-nate-ph", &any_order), expected);
+    assert_eq!(
+        schema.parse("ABC123-nate-ph"),
+        Err(UnexpectedTag("nate".to_string()))
+    );
+    assert_eq!(
+        schema.parse_with("ABC123-nate-boop", &any_order),
+        Err(UnexpectedTag("boop".to_string()))
+    );
+    assert!(matches!(
+        schema.parse_with("ABC123-nate-_", &any_order),
+        Err(RequirementMismatch { got: 0, .. })
+    ));
+}
+
+#[test]
+fn validate_partial() {
+    let schema = crate::schema::compile_schema(
+        r#"schema "-" "_" [ category "Media" (exactly 1) ['art', 'photo'/'ph', 'video'/'v'], category "People" (at_least 0) ['nate', 'kim'], category "Place" (at_most 2) ['home', 'park']]"#,
+    )
+    .unwrap();
+
+    assert_eq!(schema.validate_partial("ABC"), Ok(PartialParse::default()));
+    assert_eq!(
+        schema.validate_partial("ABC123-"),
+        Ok(PartialParse {
+            complete: vec![],
+            in_progress: Some("Media".to_string()),
+            next: vec!["art".to_string(), "ph".to_string(), "v".to_string()],
+        })
+    );
+    assert_eq!(
+        schema.validate_partial("ABC123-ph-"),
+        Ok(PartialParse {
+            complete: vec!["Media".to_string()],
+            in_progress: Some("People".to_string()),
+            next: vec!["_".to_string(), "nate".to_string(), "kim".to_string()],
+        })
+    );
+    assert_eq!(
+        schema.validate_partial("ABC123-ph-kim-"),
+        Ok(PartialParse {
+            complete: vec!["Media".to_string()],
+            in_progress: Some("People".to_string()),
+            next: vec![
+                "nate".to_string(),
+                "_".to_string(),
+                "home".to_string(),
+                "park".to_string()
+            ],
+        })
+    );
+    assert_eq!(
+        schema.validate_partial("ABC123-ph-kim-p"),
+        Ok(PartialParse {
+            complete: vec!["Media".to_string()],
+            in_progress: Some("People".to_string()),
+            next: vec!["park".to_string()],
+        })
+    );
+    assert_eq!(
+        schema.validate_partial("ABC123-ph-_-home-park-"),
+        Ok(PartialParse {
+            complete: vec![
+                "Media".to_string(),
+                "People".to_string(),
+                "Place".to_string()
+            ],
+            in_progress: None,
+            next: vec![],
+        })
+    );
+    assert_eq!(
+        schema.validate_partial("ABC123-boop-"),
+        Err(UnexpectedTag("boop".to_string()))
+    );
+
+    // an id in both the current and next categories is only offered once
+    let shared = crate::schema::compile_schema(
+        r#"schema "-" "_" [ category "Media" (exactly 1) ['art'], category "People" (at_least 0) ['nate', 'kim'], category "Place" (at_most 2) ['kim', 'home']]"#,
+    )
+    .unwrap();
+    assert_eq!(
+        shared.validate_partial("ABC123-art-nate-").map(|p| p.next),
+        Ok(vec!["kim".to_string(), "_".to_string(), "home".to_string()])
+    );
+}
+
+#[test]
+fn parse_labeled() {
+    use super::generate_with_salt;
+
+    let mut schema = crate::schema::compile_schema(
+        r#"schema "-" "_" [ category "Media" (exactly 1) ['art', 'photo'/'ph', 'video'/'v'], category "People" (at_least 0) ['nate', 'kim']]"#,
+    )
+    .unwrap();
+    schema.labeled = true;
+    let mut state = to_empty_state(&schema);
+    state[0].1[1].1 = true;
+
+    for people in [vec![], vec![0], vec![0, 1]] {
+        let mut state = state.clone();
+        for i in people {
+            state[1].1[i].1 = true;
+        }
+        let name = generate_with_salt(&schema, &state, "ABC123").unwrap();
+        assert_eq!(schema.parse(&name), Ok(state));
+    }
+
+    state[1].1[1].1 = true;
+    assert_eq!(schema.parse("ABC123-People.kim-Media.ph"), Ok(state));
+    assert_eq!(
+        schema.parse("ABC123-Media.ph-Place.home"),
+        Err(UnexpectedTag("Place.home".to_string()))
+    );
+    assert_eq!(
+        schema.parse("ABC123-ph"),
+        Err(UnexpectedTag("ph".to_string()))
+    );
+}
+
+#[test]
+fn parse_collecting() {
+    let schema = test_schema();
+    let mut expected = to_empty_state(&schema);
+    expected[0].1[1].1 = true;
+    expected[1].1[0].1 = true;
+
+    assert_eq!(
+        schema.parse_collecting("ABC123-ph-nate"),
+        (Some(expected.clone()), vec![])
+    );
+    assert_eq!(
+        schema.parse_collecting("ABC123-old-ph-nate-stray"),
+        (Some(expected), vec!["old".to_string(), "stray".to_string()])
+    );
+    // the known tags alone don't satisfy the schema
+    assert_eq!(
+        schema.parse_collecting("ABC123-old-nate-stray"),
+        (None, vec!["old".to_string(), "stray".to_string()])
+    );
+    assert_eq!(schema.parse_collecting("nope"), (None, vec![]));
+}
+
+#[test]
+fn parse_into() {
+    let schema = test_schema();
+    let mut buf = vec![];
+    assert_eq!(schema.parse_into("ABC123-ph-nate", &mut buf), Ok(()));
+    assert_eq!(Ok(buf.clone()), schema.parse("ABC123-ph-nate"));
+    // previously selected tags don't leak into the next parse
+    assert_eq!(schema.parse_into("ABC123-v-_", &mut buf), Ok(()));
+    assert_eq!(Ok(buf.clone()), schema.parse("ABC123-v-_"));
+    assert_eq!(
+        schema.parse_into("ABC123-boop", &mut buf),
+        Err(UnexpectedTag("boop".to_string()))
+    );
+}
+
+#[test]
+fn suggest() {
+    let schema = crate::schema::compile_schema(
+        r#"schema "-" "_" [ category "Media" (exactly 1) ['art', 'photo', 'video'/'v'], category "People" (at_least 0) ['nate']]"#,
+    )
+    .unwrap();
+    assert_eq!(schema.suggest("ABC123-phto-_"), vec!["ABC123-photo-_"]);
+    assert_eq!(
+        schema.suggest("ABC123-photo-nat"),
+        vec!["ABC123-photo-nate"]
+    );
+    // nothing to fix
+    assert_eq!(schema.suggest("ABC123-photo-_"), Vec::<String>::new());
+    // headers with no tags after them
+    assert_eq!(schema.suggest("ABC123"), Vec::<String>::new());
+    let mut versioned = schema.clone();
+    versioned.version = Some(2);
+    assert_eq!(versioned.suggest("ABC123-v2"), Vec::<String>::new());
+    assert_eq!(
+        versioned.suggest("ABC123-v2-phto-_"),
+        vec!["ABC123-v2-photo-_"]
+    );
+    let mut trailing = schema.clone();
+    trailing.salt.position = SaltPosition::Trailing;
+    assert_eq!(trailing.suggest("phto-_-ABC123"), vec!["photo-_-ABC123"]);
+    assert_eq!(levenshtein("kitten", "sitting"), 3);
+    assert_eq!(levenshtein("", "abc"), 3);
+}
+
+#[test]
+fn parse_collecting_multichar_delim() {
+    let mut schema = test_schema();
+    schema.delim = crate::schema::Delim::from("--");
+    let mut expected = to_empty_state(&schema);
+    expected[0].1[1].1 = true;
+    expected[1].1[0].1 = true;
+    assert_eq!(schema.parse("ABC123--ph--nate"), Ok(expected.clone()));
+    assert_eq!(
+        schema.parse_collecting("ABC123--ph--boop--nate"),
+        (Some(expected), vec!["boop".to_string()])
+    );
+    assert_eq!(
+        schema.parse_collecting("ABC123--boop"),
+        (None, vec!["boop".to_string()])
+    );
+}
+
+#[test]
+fn parse_versioned() {
+    let mut schema = test_schema();
+    let mut expected = to_empty_state(&schema);
+    expected[0].1[1].1 = true;
+    let unversioned = crate::filename::generate_with_salt(&schema, &expected, "ABC123").unwrap();
+    assert_eq!(schema.parse(&unversioned), Ok(expected.clone()));
+
+    schema.version = Some(2);
+    let versioned = crate::filename::generate_with_salt(&schema, &expected, "ABC123").unwrap();
+    assert_eq!(versioned, "ABC123-v2-ph-_");
+    assert_eq!(schema.parse(&versioned), Ok(expected));
+    assert_eq!(
+        schema.parse(&unversioned),
+        Err(VersionMismatch {
+            expected: 2,
+            got: "ph".to_string()
+        })
+    );
+    assert_eq!(
+        schema.parse("ABC123-v3-ph-_"),
+        Err(VersionMismatch {
+            expected: 2,
+            got: "v3".to_string()
+        })
+    );
+    assert_eq!(schema.parse("ABC123-"), Err(UnexpectedEnd));
+    assert_eq!(schema.suggest("ABC123-v2-phh-_"), vec!["ABC123-v2-ph-_"]);
+    assert_eq!(
+        schema.validate_partial("ABC123-v"),
+        Ok(PartialParse::default())
+    );
+    assert_eq!(
+        schema.validate_partial("ABC123-v2-").map(|p| p.next),
+        Ok(vec!["art".to_string(), "ph".to_string(), "v".to_string()])
+    );
+}
+
+#[test]
+fn parse_freeform() {
+    let mut schema = crate::schema::compile_schema(
+        r#"schema "-" "_" [ category "Media" (exactly 1) ['art', 'photo'/'ph'], freeform "Tags" (at_most 2), category "People" (at_least 0) ['nate']]"#,
+    )
+    .unwrap();
+    let state = schema.parse("ABC123-ph-beach-sunset-nate").unwrap();
+    assert_eq!(
+        state[1]
+            .1
+            .iter()
+            .map(|(kw, tf)| (kw.id.as_str(), *tf))
+            .collect::<Vec<_>>(),
+        vec![("beach", true), ("sunset", true)]
+    );
+    assert!(state[2].1[0].1);
+    assert_eq!(
+        crate::filename::generate_with_salt(&schema, &state, "ABC123"),
+        Ok("ABC123-ph-beach-sunset-nate".to_string())
+    );
+
+    let empty = schema.parse("ABC123-art-_-_").unwrap();
+    assert!(empty[1].1.is_empty());
+    assert!(matches!(
+        schema.parse("ABC123-art-a-b-c-_"),
+        Err(RequirementMismatch { got: 3, .. })
+    ));
+    // keywords of other categories are never freeform
+    assert_eq!(
+        schema.parse("ABC123-art-nate-_"),
+        Err(UnexpectedTag("nate".to_string()))
+    );
+
+    let any_order = ParseOptions {
+        any_order: true,
+        ..ParseOptions::default()
+    };
+    let state = schema
+        .parse_with("ABC123-nate-beach-ph", &any_order)
+        .unwrap();
+    assert_eq!(state[1].1[0].0.id, "beach");
+
+    schema.labeled = true;
+    let state = schema.parse("ABC123-Media.ph-Tags.beach-People._").unwrap();
+    assert_eq!(state[1].1[0].0.id, "beach");
+    assert_eq!(
+        schema.parse("ABC123-Media.ph-People.beach"),
+        Err(UnexpectedTag("People.beach".to_string()))
+    );
+}
+
+#[test]
+fn parse_checksum_salt() {
+    let mut schema = test_schema();
+    schema.salt.mode = SaltMode::Checksum;
+    let mut state = to_empty_state(&schema);
+    state[0].1[1].1 = true;
+    let name = crate::filename::generate_checksummed(&schema, &state).unwrap();
+    assert_eq!(schema.parse(&name), Ok(state));
+
+    let salt = &name[..schema.salt.len];
+    let tampered = format!("{salt}-v-_");
+    assert_eq!(
+        schema.parse(&tampered),
+        Err(ChecksumMismatch {
+            expected: "v-_".to_string(),
+            got: salt.to_string()
+        })
+    );
+
+    // tags that parse but can't be generated have no checksum to compare
+    schema.max_tags_per_category = Some(0);
+    assert_eq!(
+        schema.parse(&name),
+        Err(Generate(super::GenerateFilenameError::TooManyTags {
+            category: "Media".to_string(),
+            max: 0,
+            got: 1,
+        }))
+    );
+}
+
+#[test]
+fn is_canonical() {
+    let schema = test_schema();
+    assert_eq!(schema.is_canonical("ABC123-ph-nate"), Ok(true));
+    assert_eq!(schema.is_canonical("ABC123-ph-_"), Ok(true));
+    assert_eq!(schema.is_canonical("ABC123-nate-ph"), Ok(false));
+    assert_eq!(schema.is_canonical("ABC123-ph"), Ok(false));
+    assert_eq!(
+        schema.is_canonical("ABC123-ph-boop"),
+        Err(UnexpectedTag("boop".to_string()))
+    );
+}
+
+#[test]
+fn parse_trailing_salt() {
+    let mut schema = test_schema();
+    schema.salt.position = SaltPosition::Trailing;
+    let mut state = to_empty_state(&schema);
+    state[0].1[1].1 = true;
+    state[1].1[0].1 = true;
+    let name = crate::filename::generate_with_salt(&schema, &state, "ABC123").unwrap();
+    assert_eq!(name, "ph-nate-ABC123");
+    assert_eq!(schema.parse(&name), Ok(state.clone()));
+    assert_eq!(schema.extract_salt(&name), Ok("ABC123".to_string()));
+    assert_eq!(schema.is_canonical(&name), Ok(true));
+    assert_eq!(schema.suggest("phh-nate-ABC123"), vec!["ph-nate-ABC123"]);
+    assert_eq!(
+        schema.parse("ABC123-ph-nate"),
+        Err(InvalidSalt("h-nate".to_string()))
+    );
+    assert_eq!(
+        schema.validate_partial("ph-").map(|p| p.in_progress),
+        Ok(Some("People".to_string()))
+    );
+
+    schema.version = Some(2);
+    let name = crate::filename::generate_with_salt(&schema, &state, "ABC123").unwrap();
+    assert_eq!(name, "v2-ph-nate-ABC123");
+    assert_eq!(schema.parse(&name), Ok(state));
+
+    schema.version = None;
+    schema.salt.charset = "ABC-";
+    assert_eq!(
+        schema.parse("ph-_-AB-CAB"),
+        Err(DelimiterInSalt("AB-CAB".to_string()))
+    );
+}
+
+#[test]
+fn prepared_agrees_with_parse() {
+    let schema = test_schema();
+    let prepared = PreparedSchema::new(&schema);
+    for name in [
+        "ABC123-art",
+        "ABC123-ph-nate",
+        "ABC123-v-_",
+        "ABC123-art-ph",
+        "ABC123-nate",
+        "ABC123-_-nate",
+        "ABC123",
+        "ABC123-art-nate-nate",
+    ] {
+        assert_eq!(prepared.parse(name), schema.parse(name), "{name}");
+    }
+    let any_order = ParseOptions {
+        any_order: true,
+        ..ParseOptions::default()
+    };
+    assert_eq!(
+        prepared.parse_with("ABC123-nate-ph", &any_order),
+        schema.parse_with("ABC123-nate-ph", &any_order)
+    );
+    assert!(prepared.parse_with("ABC123-nate-ph", &any_order).is_ok());
+}
+
+#[test]
+fn parse_intra_delim() {
+    let schema = crate::schema::compile_schema(
+        r#"schema "-" "_" [ category "Media" (exactly 1) ['art', 'photo'/'ph'], category "Subject" (at_least 0) "+" ['plants', 'animals', 'people'] ]"#,
+    )
+    .unwrap();
+    let mut state = to_empty_state(&schema);
+    state[0].1[0].1 = true;
+    state[1].1[0].1 = true;
+    state[1].1[1].1 = true;
+    let name = super::generate_with_salt(&schema, &state, "ABC123").unwrap();
+    assert_eq!(name, "ABC123-art-plants+animals");
+    assert_eq!(schema.parse(&name), Ok(state.clone()));
+    assert_eq!(schema.is_canonical(&name), Ok(true));
+
+    state[1].1[1].1 = false;
+    let name = super::generate_with_salt(&schema, &state, "ABC123").unwrap();
+    assert_eq!(name, "ABC123-art-plants");
+    assert_eq!(schema.parse(&name), Ok(state));
+    assert_eq!(
+        schema.parse("ABC123-art-plants+ph"),
+        Err(UnexpectedTag("ph".to_string()))
+    );
+}
+
+#[test]
+fn parse_tag_lists() {
+    let schema = test_schema();
+    assert_eq!(
+        schema.parse_tags("ABC123-ph-nate"),
+        Ok(vec![
+            ("Media".to_string(), vec!["ph".to_string()]),
+            ("People".to_string(), vec!["nate".to_string()]),
+        ])
+    );
+    assert_eq!(
+        schema.parse_tags("ABC123-v-_"),
+        Ok(vec![
+            ("Media".to_string(), vec!["v".to_string()]),
+            ("People".to_string(), vec![]),
+        ])
+    );
+    assert!(schema.parse_tags("ABC123-_-nate").is_err());
+}
+
+#[test]
+fn parse_empty_modes() {
+    let mut schema = crate::schema::compile_schema(
+        r#"schema "-" "_" [ category "People" ['nate', 'kim'], category "Place" ['home', 'park'], category "Media" (exactly 1) ['art', 'ph'] ]"#,
+    )
+    .unwrap();
+    let mut state = to_empty_state(&schema);
+    state[2].1[0].1 = true;
+    let name = super::generate_with_salt(&schema, &state, "ABC123").unwrap();
+    assert_eq!(name, "ABC123-_-_-art");
+    assert_eq!(schema.parse(&name), Ok(state.clone()));
+    assert_eq!(schema.computed_length(&state), Ok(name.len()));
+
+    schema.empty_mode = EmptyMode::Collapse;
+    let name = super::generate_with_salt(&schema, &state, "ABC123").unwrap();
+    assert_eq!(name, "ABC123-art");
+    assert_eq!(schema.parse(&name), Ok(state.clone()));
+    assert_eq!(schema.computed_length(&state), Ok(name.len()));
+
+    state[2].1[0].1 = false;
+    state[2].1[1].1 = true;
+    state[0].1[1].1 = true;
+    let name = super::generate_with_salt(&schema, &state, "ABC123").unwrap();
+    assert_eq!(name, "ABC123-kim-ph");
+    assert_eq!(schema.parse(&name), Ok(state));
+
+    schema.categories[2].0.requirement = crate::schema::Requirement::AtLeast(0);
+    let state = to_empty_state(&schema);
+    let name = super::generate_with_salt(&schema, &state, "ABC123").unwrap();
+    assert_eq!(name, "ABC123-_");
+    assert_eq!(schema.parse(&name), Ok(state.clone()));
+    assert_eq!(schema.computed_length(&state), Ok(name.len()));
+    assert_eq!(schema.length_bounds().0, name.len());
+}
+
+#[test]
+fn parse_repeatable() {
+    let schema = crate::schema::compile_schema(
+        r#"schema "-" "_" [ repeatable (category "Subject" ['plants', 'animals', 'sky']), category "Media" (exactly 1) ['art', 'ph'], category "People" ['nate'] ]"#,
+    )
+    .unwrap();
+    let mut state = to_empty_state(&schema);
+    state[0].1[0].1 = true;
+    state[0].1[2].1 = true;
+    state[1].1[1].1 = true;
+    assert_eq!(schema.parse("ABC123-plants-ph-sky-_"), Ok(state.clone()));
+    assert_eq!(schema.parse("ABC123-plants-ph-_-sky"), Ok(state.clone()));
+    assert_eq!(schema.parse("ABC123-plants-sky-ph-_"), Ok(state));
+    assert_eq!(
+        schema.parse("ABC123-plants-ph-plants-_"),
+        Err(UnexpectedTag("plants".to_string()))
+    );
+
+    // categories that aren't repeatable stay contiguous
+    assert_eq!(
+        schema.parse("ABC123-_-ph-nate-art"),
+        Err(UnexpectedTag("art".to_string()))
+    );
+}
+
+#[test]
+fn parse_escaped_delim() {
+    let mut schema = crate::schema::compile_schema(
+        r#"schema "-" "_" [ category "Media" (exactly 1) ['hand-made', 'art'], category "People" ['nate'], freeform "Tags" (at_least 0) ]"#,
+    )
+    .unwrap();
+    schema.escape_delim = true;
+    assert_eq!(schema.validate(), Ok(()));
+
+    let mut state = to_empty_state(&schema);
+    state[0].1[0].1 = true;
+    state[1].1[0].1 = true;
+    let name = super::generate_with_salt(&schema, &state, "ABC123").unwrap();
+    assert_eq!(name, "ABC123-hand--made-nate-_");
+    assert_eq!(schema.parse(&name), Ok(state.clone()));
+    assert_eq!(schema.computed_length(&state), Ok(name.len()));
+
+    state[2].1.push((freeform_keyword("well-lit"), true));
+    let name = super::generate_with_salt(&schema, &state, "ABC123").unwrap();
+    assert_eq!(name, "ABC123-hand--made-nate-well--lit");
+    assert_eq!(schema.parse(&name), Ok(state));
+
+    // without escaping, the doubled delimiter is an empty tag
+    schema.escape_delim = false;
+    assert!(schema.parse("ABC123-hand--made-nate-_").is_err());
+}
+
+#[test]
+fn first_diff() {
+    let schema = crate::schema::compile_schema(
+        r#"schema "-" "_" [ category "Media" (at_least 1) ['art', 'photo'/'ph', 'video'/'v'], category "People" ['nate', 'kim'] ]"#,
+    )
+    .unwrap();
+    assert_eq!(schema.first_diff("ABC123-art-ph-nate"), Ok(None));
+    assert_eq!(
+        schema.first_diff("ABC123-art-ph-kim-nate"),
+        Ok(Some((
+            14,
+            "tags out of order at offset 14: expected \"nate\", found \"kim\"".to_string()
+        )))
+    );
+    assert_eq!(
+        schema
+            .first_diff("ABC123-ph-art-_")
+            .map(|diff| diff.map(|(at, _)| at)),
+        Ok(Some(7))
+    );
+    assert!(schema.first_diff("ABC123-nope").is_err());
+    let mut capped = schema.clone();
+    capped.max_tags_per_category = Some(1);
+    assert_eq!(
+        capped.first_diff("ABC123-art-ph-nate"),
+        Err(Generate(super::GenerateFilenameError::TooManyTags {
+            category: "Media".to_string(),
+            max: 1,
+            got: 2,
+        }))
+    );
+}
+
+#[test]
+fn parse_trim_whitespace() {
+    let schema = test_schema();
+    let trim = ParseOptions {
+        trim_whitespace: true,
+        ..ParseOptions::default()
+    };
+    let padded = "ABC123 - art -  nate ";
+    assert!(schema.parse(padded).is_err());
+    assert_eq!(
+        schema.parse_with(padded, &trim),
+        schema.parse("ABC123-art-nate")
+    );
+    assert!(schema.parse_with(padded, &trim).is_ok());
+    // whitespace inside a tag is still part of the tag
+    assert!(schema.parse_with("ABC123-a rt-nate", &trim).is_err());
+}
+
+#[test]
+fn parse_prefix() {
+    let schema = test_schema();
+    assert_eq!(
+        schema.parse_prefix("ABC123-art-nate-from the beach trip"),
+        Ok((
+            schema.parse("ABC123-art-nate").unwrap(),
+            "from the beach trip"
+        ))
+    );
+    assert_eq!(
+        schema.parse_prefix("ABC123-art-_-draft-2"),
+        Ok((schema.parse("ABC123-art-_").unwrap(), "draft-2"))
+    );
+    assert_eq!(
+        schema.parse_prefix("ABC123-art-nate"),
+        Ok((schema.parse("ABC123-art-nate").unwrap(), ""))
+    );
+    assert_eq!(
+        schema.parse_prefix("ABC123-draft-art"),
+        Err(schema.parse("ABC123-draft-art").unwrap_err())
+    );
+}
+
+#[test]
+fn diff_filenames() {
+    let schema = crate::schema::compile_schema(
+        r#"schema "-" "_" [ category "Media" (exactly 1) ['art', 'photo'/'ph'], category "People" (at_least 0) ['nate', 'kim', 'sam'] ]"#,
+    )
+    .unwrap();
+    assert_eq!(
+        schema.diff_filenames("ABC123-ph-nate-kim", "XYZ789-ph-kim-sam"),
+        Ok(vec![TagChange {
+            category: "People".to_string(),
+            added: vec!["sam".to_string()],
+            removed: vec!["nate".to_string()],
+        }])
+    );
+    assert_eq!(
+        schema.diff_filenames("ABC123-ph-nate", "XYZ789-ph-nate"),
+        Ok(vec![])
+    );
+    assert!(schema
+        .diff_filenames("ABC123-ph-nate", "ABC123-nate")
+        .is_err());
+}
+
+#[test]
+fn parse_salt_ignore_case() {
+    let mut schema = test_schema();
+    let ignore_case = ParseOptions {
+        salt_ignore_case: true,
+        ..ParseOptions::default()
+    };
+    let expected = schema.parse("ABC123-art-nate");
+    assert_eq!(
+        schema.parse("abc123-art-nate"),
+        Err(InvalidSalt("abc123".to_string()))
+    );
+    assert_eq!(schema.parse_with("abc123-art-nate", &ignore_case), expected);
+    assert_eq!(schema.parse_with("AbC123-art-nate", &ignore_case), expected);
+    // tags stay case sensitive
+    assert!(schema.parse_with("abc123-ART-nate", &ignore_case).is_err());
+
+    schema.salt.charset = "0123456789abcdef";
+    schema.salt.position = SaltPosition::Trailing;
+    let expected = schema.parse("art-nate-abc123");
+    assert!(expected.is_ok());
+    assert_eq!(schema.parse_with("art-nate-ABC123", &ignore_case), expected);
+    assert_eq!(
+        schema.parse_with("ABC123", &ignore_case),
+        schema.parse("abc123")
+    );
+}
+
+#[cfg(feature = "rayon")]
+#[test]
+fn parse_par() {
+    let schema = test_schema();
+    let names = [
+        "ABC123-art-nate",
+        "XYZ789-ph-_",
+        "ABC123-nate",
+        "abc123-v-nate",
+    ];
+    let filenames: Vec<String> = names
+        .iter()
+        .cycle()
+        .take(200)
+        .map(|x| x.to_string())
+        .collect();
+    let sequential: Vec<Result<State>> = filenames.iter().map(|x| schema.parse(x)).collect();
+    assert!(sequential.iter().any(Result::is_ok));
+    assert!(sequential.iter().any(Result::is_err));
+    assert_eq!(schema.parse_par(&filenames), sequential);
+}
@@ -4,14 +4,13 @@ use crate::{
 };
 use std::{
     fs,
+    io::{self, BufRead, Write},
     path::{Path, PathBuf},
 };
 
 pub fn read_schema_file(path: &Path) -> Result<Schema> {
     let contents = fs::read_to_string(path).map_err(Error::FailedToReadContents)?;
-    let parsed = schema::parse::parse(&contents)?;
-    let schema = schema::typecheck::typecheck(parsed)?;
-    Ok(schema)
+    schema::compile_schema(&contents)
 }
 
 /// collects filenames of all non-directory entries in the given directory.
@@ -29,6 +28,35 @@ pub fn collect_filenames(dir: &dyn AsRef<Path>) -> Result<Vec<PathBuf>> {
     Ok(files)
 }
 
+/// checks one filename per line, writing `OK` or `FAIL: reason` for each as it's read.
+/// Any extension is ignored.
+pub fn check_stream<R: BufRead, W: Write>(schema: &Schema, r: R, mut w: W) -> io::Result<()> {
+    for line in r.lines() {
+        let line = line?;
+        let stem = Path::new(&line)
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .unwrap_or(&line);
+        match schema.parse(stem) {
+            Ok(_) => writeln!(w, "OK")?,
+            Err(e) => writeln!(w, "FAIL: {e}")?,
+        }
+    }
+    w.flush()
+}
+
+#[test]
+fn check_stream_lines() {
+    let schema = schema::compile_schema(schema::TEST_SCHEMA).unwrap();
+    let input = io::Cursor::new("ABC123-ph-_.jpg\nABC123-ph-boop\r\nABC123-v-nate\n");
+    let mut out = vec![];
+    check_stream(&schema, input, &mut out).unwrap();
+    assert_eq!(
+        String::from_utf8(out).unwrap(),
+        "OK\nFAIL: Unexpected tag \"boop\".\nOK\n"
+    );
+}
+
 #[cfg(test)]
 /// used to test file system limitations for cross-platform compatibility
 mod limitations {
@@ -47,7 +75,7 @@ mod limitations {
             "子曰。學而時習之、不亦說乎。有朋自遠方來、不亦樂乎。人不知而不慍、不亦君子乎。有子曰。其爲人也孝弟、而好犯上者、鮮矣。不好犯上、而好作亂者、未之有也。君子務本、本立而--.jpg",
         ];
         for s in utf8_byte_255 {
-            assert_eq!(255, s.bytes().len());
+            assert_eq!(255, s.len());
         }
 
         let utf16_byte_254 = [
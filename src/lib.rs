@@ -4,17 +4,34 @@ pub mod filename;
 pub mod fs;
 pub mod schema;
 
+pub use app::to_empty_state;
+pub use error::{Error, Result};
+pub use filename::{
+    generate, generate_with_salt,
+    parse::{ParseOptions, PreparedSchema, TagChange},
+    EmptyMode, FilenameParseError, GenerateFilenameError, SaltMode, SaltPosition, SaltSpec,
+};
+pub use schema::{
+    compile_schema, parse::parse as parse_schema, typecheck::typecheck, Category, Delim, Keyword,
+    Requirement, Schema, SchemaBuilder, SchemaError, SchemaParseError, SchemaTypeCheckError,
+};
+
 use app::AppConfig;
 use clap::Parser;
-use error::{Error, Result};
-use schema::{Category, Keyword};
 use std::path::PathBuf;
 
-type State = Vec<(Category, Vec<(Keyword, bool)>)>;
+/// every category of a schema with each of its keywords and whether it's selected.
+pub type State = Vec<(Category, Vec<(Keyword, bool)>)>;
 
 #[derive(Parser, Debug, Clone)]
 struct Args {
     working_dir: PathBuf,
+    /// schema source text to use instead of the working directory's schema.q
+    #[arg(long)]
+    schema_inline: Option<String>,
+    /// print the filename of the schema's minimal selection instead of opening the GUI
+    #[arg(long)]
+    print_name: bool,
 }
 
 pub fn run() -> Result<()> {
@@ -32,8 +49,18 @@ pub fn run() -> Result<()> {
 
     // run the app
     let working_dir = std::fs::canonicalize(args.working_dir).map_err(Error::PathErr)?;
-    let mut schema_path = working_dir.clone();
-    schema_path.push("schema.q");
-    let schema = fs::read_schema_file(&schema_path)?;
+    let schema = match args.schema_inline {
+        Some(src) => schema::compile_schema(&src)?,
+        None => {
+            let mut schema_path = working_dir.clone();
+            schema_path.push("schema.q");
+            fs::read_schema_file(&schema_path)?
+        }
+    };
+    if args.print_name {
+        let name = filename::generate(&schema, &schema.minimal_selection())?;
+        println!("{name}");
+        return Ok(());
+    }
     AppConfig::run_with(schema, working_dir)
 }
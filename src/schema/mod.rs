@@ -1,29 +1,921 @@
 pub mod parse;
 pub mod typecheck;
 
-use std::{error::Error as StdError, fmt};
+use crate::{
+    error::Result,
+    filename::{EmptyMode, GenerateFilenameError, SaltSpec, LABEL_DELIM},
+    State,
+};
+use std::{
+    borrow::Cow,
+    collections::{BTreeSet, HashSet},
+    error::Error as StdError,
+    fmt,
+    hash::{Hash, Hasher},
+    ops::Range,
+};
 use typecheck::Type;
 
 #[derive(Clone, Debug, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct Schema {
-    pub delim: String,
+    pub delim: Delim,
     pub empty: String,
+    pub salt: SaltSpec,
+    /// prefix every tag with its category name so filenames are self-describing
+    pub labeled: bool,
+    /// a doubled delimiter in a filename is one literal delimiter in a tag rather than a separator
+    pub escape_delim: bool,
+    pub empty_mode: EmptyMode,
+    /// every filename must have at least one tag in some category
+    pub require_any_tag: bool,
+    /// a cap on the tags in any one category, on top of each category's own requirement
+    pub max_tags_per_category: Option<usize>,
+    /// typed text matches keyword ids regardless of case when completing. Filenames still
+    /// have to match ids exactly.
+    pub case_insensitive: bool,
+    /// written as `v{n}` right after the salt so filenames identify the schema that generated them
+    pub version: Option<u32>,
     pub categories: Vec<(Category, Vec<Keyword>)>,
 }
 
+impl Schema {
+    /// fraction of categories in the state whose requirements are currently satisfied.
+    pub fn completion(&self, state: &State) -> f32 {
+        let satisfied = self.satisfied(state);
+        if satisfied.is_empty() {
+            return 1.0;
+        }
+        satisfied.iter().filter(|x| **x).count() as f32 / satisfied.len() as f32
+    }
+
+    /// shortest and longest possible byte lengths of a conforming filename without its extension.
+    /// freeform categories take tags of any length, so they make the longest `usize::MAX`.
+    pub fn length_bounds(&self) -> (usize, usize) {
+        let version = self
+            .version_segment()
+            .map_or(0, |v| v.len() + self.delim.len());
+        let mut min = self.salt.len + version;
+        let mut max = self.salt.len + version;
+        for (cat, kws) in &self.categories {
+            let mut lens: Vec<usize> = kws.iter().map(|kw| self.escape_tag(&kw.id).len()).collect();
+            lens.sort_unstable();
+            let lo = cat.requirement.min_count().min(lens.len());
+            let hi = cat
+                .requirement
+                .max_count()
+                .unwrap_or(lens.len())
+                .min(lens.len());
+            let label = if self.labeled {
+                cat.name.len() + LABEL_DELIM.len()
+            } else {
+                0
+            };
+            let sep = cat.intra_delim.as_ref().unwrap_or(&self.delim).len();
+            // includes the delimiter before the segment
+            let segment_len = |ids: &[usize]| match ids.len() {
+                0 if self.empty_mode == EmptyMode::Collapse => 0,
+                0 => self.delim.len() + label + self.empty.len(),
+                n => self.delim.len() + ids.iter().sum::<usize>() + n * label + (n - 1) * sep,
+            };
+            let (cat_min, cat_max) = if cat.freeform {
+                // every freeform tag is at least one byte
+                let n = cat.requirement.min_count();
+                let ones = vec![1; n];
+                (segment_len(&ones), usize::MAX)
+            } else {
+                (lo..=hi.max(lo))
+                    .map(|k| {
+                        (
+                            segment_len(&lens[..k]),
+                            segment_len(&lens[lens.len() - k..]),
+                        )
+                    })
+                    .fold((usize::MAX, 0), |(lo, hi), (a, b)| (lo.min(a), hi.max(b)))
+            };
+            min += cat_min;
+            max = max.saturating_add(cat_max);
+        }
+        if self.categories.is_empty() {
+            min += self.delim.len();
+            max = max.saturating_add(self.delim.len());
+        }
+        // every category collapsing away leaves a single placeholder
+        let collapsed = self.salt.len + version;
+        if let (true, Some((cat, _))) = (min == collapsed, self.categories.first()) {
+            let label = if self.labeled {
+                cat.name.len() + LABEL_DELIM.len()
+            } else {
+                0
+            };
+            min += self.delim.len() + label + self.empty.len();
+            max = max.max(min);
+        }
+        (min, max)
+    }
+
+    /// byte length of the filename the selection would generate without its extension,
+    /// worked out from the tag lengths without building the filename.
+    pub fn computed_length(
+        &self,
+        state: &State,
+    ) -> std::result::Result<usize, GenerateFilenameError> {
+        let mut len = self.salt.len
+            + self
+                .version_segment()
+                .map_or(0, |v| v.len() + self.delim.len());
+        self.validate_state(state)?;
+        let mut tagged = false;
+        for (cat, kws) in state {
+            let ids: Vec<usize> = kws
+                .iter()
+                .filter(|(_, tf)| *tf)
+                .map(|(kw, _)| self.escape_tag(&kw.id).len())
+                .collect();
+            let label = if self.labeled {
+                cat.name.len() + LABEL_DELIM.len()
+            } else {
+                0
+            };
+            let sep = cat.intra_delim.as_ref().unwrap_or(&self.delim).len();
+            len += match ids.len() {
+                0 if self.empty_mode == EmptyMode::Collapse => continue,
+                0 => label + self.empty.len(),
+                n => ids.iter().sum::<usize>() + n * label + (n - 1) * sep,
+            } + self.delim.len();
+            tagged = true;
+        }
+        // every category collapsing away leaves a single placeholder
+        match state.first() {
+            None => len += self.delim.len(),
+            Some((cat, _)) if !tagged => {
+                if self.labeled {
+                    len += cat.name.len() + LABEL_DELIM.len();
+                }
+                len += self.delim.len() + self.empty.len();
+            }
+            Some(_) => (),
+        }
+        Ok(len)
+    }
+
+    /// renders the categories as an aligned table with columns for the category, its requirement, and its keywords.
+    pub fn to_table(&self) -> String {
+        let header = [
+            "Category".to_string(),
+            "Requirement".to_string(),
+            "Keywords".to_string(),
+        ];
+        let rows: Vec<[String; 3]> = self
+            .categories
+            .iter()
+            .map(|(cat, kws)| {
+                let kws: Vec<String> = kws.iter().map(Keyword::to_string).collect();
+                let kws = if cat.freeform {
+                    "(any)".to_string()
+                } else {
+                    kws.join(", ")
+                };
+                [cat.name.clone(), cat.requirement.to_string(), kws]
+            })
+            .collect();
+
+        let width = |col: usize| {
+            std::iter::once(&header)
+                .chain(&rows)
+                .map(|row| row[col].chars().count())
+                .max()
+                .unwrap_or(0)
+        };
+        let (w0, w1, w2) = (width(0), width(1), width(2));
+        let line = |row: &[String; 3]| format!("{:<w0$} | {:<w1$} | {}\n", row[0], row[1], row[2]);
+
+        let mut table = line(&header);
+        table.push_str(&format!(
+            "{}-+-{}-+-{}\n",
+            "-".repeat(w0),
+            "-".repeat(w1),
+            "-".repeat(w2)
+        ));
+        for row in &rows {
+            table.push_str(&line(row));
+        }
+        table
+    }
+
+    /// starts a schema with no categories and the default salt.
+    pub fn builder(delim: &str, empty: &str) -> SchemaBuilder {
+        SchemaBuilder {
+            schema: Schema {
+                delim: Delim::from(delim),
+                empty: empty.to_string(),
+                salt: SaltSpec::default(),
+                labeled: false,
+                escape_delim: false,
+                empty_mode: EmptyMode::default(),
+                require_any_tag: false,
+                max_tags_per_category: None,
+                case_insensitive: false,
+                version: None,
+                categories: vec![],
+            },
+        }
+    }
+
+    /// checks for schemas that compile but can't generate or parse filenames reliably,
+    /// returning every problem found.
+    pub fn validate(&self) -> std::result::Result<(), Vec<SchemaError>> {
+        self.validate_with(&ValidateOptions::default())
+    }
+
+    pub fn validate_with(
+        &self,
+        opts: &ValidateOptions,
+    ) -> std::result::Result<(), Vec<SchemaError>> {
+        let mut errors = vec![];
+        let delims = self.delimiters();
+        for (i, (cat, kws)) in self.categories.iter().enumerate() {
+            // labeled filenames write category names next to their tags
+            if self.labeled
+                && (cat.name.is_empty()
+                    || cat.name.contains(['/', '\\'])
+                    || delims.iter().any(|d| cat.name.contains(d.as_str())))
+            {
+                errors.push(SchemaError::IllegalCategoryName(cat.name.clone()));
+            }
+            if !opts.allow_empty_category && cat.requirement.max_count() == Some(0) {
+                errors.push(SchemaError::EmptyCategory(cat.name.clone()));
+            }
+            if self.categories[..i]
+                .iter()
+                .any(|(other, _)| other.name == cat.name)
+            {
+                errors.push(SchemaError::DuplicateCategory(cat.name.clone()));
+            }
+            let choosable = kws.iter().filter(|kw| !kw.draft).count();
+            if let (false, Err(e)) = (cat.freeform, cat.requirement.check_against(choosable)) {
+                errors.push(SchemaError::Infeasible {
+                    category: cat.name.clone(),
+                    requirement: e.requirement,
+                    available: e.available,
+                });
+            }
+            for kw in kws {
+                let (category, id) = (cat.name.clone(), kw.id.clone());
+                if kw.id.is_empty() || kw.id == self.empty {
+                    errors.push(SchemaError::EmptyKeyword { category, id });
+                } else if (self.delim.is_in(&kw.id)
+                    // escaped delimiters are only ambiguous next to a separator
+                    && !(self.escape_delim
+                        && !self.delim.starts(&kw.id)
+                        && !self.delim.ends(&kw.id)))
+                    || self
+                        .categories
+                        .iter()
+                        .filter_map(|(cat, _)| cat.intra_delim.as_ref())
+                        .any(|intra| intra.is_in(&kw.id))
+                {
+                    errors.push(SchemaError::DelimiterInKeyword { category, id });
+                }
+            }
+        }
+        if self.salt.is_valid(&self.empty) {
+            errors.push(SchemaError::PlaceholderIsSalt(self.empty.clone()));
+        }
+        if !opts.allow_overlapping_categories {
+            errors.extend(self.overlapping_categories());
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// reports every keyword id longer than `max` bytes along with its length.
+    pub fn validate_id_lengths(&self, max: usize) -> std::result::Result<(), Vec<(String, usize)>> {
+        let long: Vec<(String, usize)> = self
+            .categories
+            .iter()
+            .flat_map(|(_, kws)| kws.iter())
+            .filter(|kw| kw.id.len() > max)
+            .map(|kw| (kw.id.clone(), kw.id.len()))
+            .collect();
+        if long.is_empty() {
+            Ok(())
+        } else {
+            Err(long)
+        }
+    }
+
+    /// a deterministic example selection that takes the first keywords of each
+    /// category, only as many as its requirement needs.
+    pub fn minimal_selection(&self) -> State {
+        let mut state = crate::app::to_empty_state(self);
+        for (cat, kws) in state.iter_mut() {
+            let n = cat.requirement.min_count();
+            if cat.freeform {
+                kws.extend((1..=n).map(|i| {
+                    let id = format!("tag{i}");
+                    let kw = Keyword {
+                        name: id.clone(),
+                        id,
+                        description: None,
+                        draft: false,
+                    };
+                    (kw, true)
+                }));
+            } else {
+                for (_, tf) in kws.iter_mut().filter(|(kw, _)| !kw.draft).take(n) {
+                    *tf = true;
+                }
+            }
+        }
+        state
+    }
+
+    /// pairs of categories where every keyword id of one is also a keyword id of the other,
+    /// so tags can be credited to the wrong category when parsing.
+    fn overlapping_categories(&self) -> Vec<SchemaError> {
+        let ids: Vec<HashSet<&str>> = self
+            .categories
+            .iter()
+            .map(|(_, kws)| kws.iter().map(|kw| kw.id.as_str()).collect())
+            .collect();
+        let mut errors = vec![];
+        for (i, (a, _)) in self.categories.iter().enumerate() {
+            for (j, (b, _)) in self.categories.iter().enumerate().skip(i + 1) {
+                if a.freeform || b.freeform || ids[i].is_empty() || ids[j].is_empty() {
+                    continue;
+                }
+                let (subset, superset) = if ids[j].is_subset(&ids[i]) {
+                    (b, a)
+                } else if ids[i].is_subset(&ids[j]) {
+                    (a, b)
+                } else {
+                    continue;
+                };
+                errors.push(SchemaError::OverlappingCategories {
+                    subset: subset.name.clone(),
+                    superset: superset.name.clone(),
+                });
+            }
+        }
+        errors
+    }
+
+    /// every category with a keyword of this id. More than one means the tag is ambiguous.
+    pub fn categories_for_tag(&self, id: &str) -> Vec<&Category> {
+        self.categories
+            .iter()
+            .filter(|(_, kws)| kws.iter().any(|kw| kw.id == id))
+            .map(|(cat, _)| cat)
+            .collect()
+    }
+
+    /// every keyword in the schema paired with the name of its category, in schema order.
+    pub fn all_keywords(&self) -> Vec<(&str, &Keyword)> {
+        self.categories
+            .iter()
+            .flat_map(|(cat, kws)| kws.iter().map(move |kw| (cat.name.as_str(), kw)))
+            .collect()
+    }
+
+    /// (category, keyword id) pairs whose ids contain the proposed delimiter, which would
+    /// split them into more than one tag if the schema switched to it.
+    pub fn would_collide_with_delim(&self, new_delim: &str) -> Vec<(String, String)> {
+        let delim = Delim::from(new_delim);
+        self.all_keywords()
+            .into_iter()
+            .filter(|(_, kw)| delim.is_in(&kw.id))
+            .map(|(cat, kw)| (cat.to_string(), kw.id.clone()))
+            .collect()
+    }
+
+    /// a hash of the schema for caching that's the same for schemas that only differ in
+    /// category order or in how equivalent requirements are written. Unlike `Hash` with the
+    /// randomly seeded default hasher, it's the same from one run to the next.
+    pub fn fingerprint(&self) -> u64 {
+        let mut canonical = self.clone();
+        for (cat, _) in canonical.categories.iter_mut() {
+            cat.requirement = cat.requirement.canonical();
+        }
+        canonical
+            .categories
+            .sort_by(|(a, _), (b, _)| a.name.cmp(&b.name));
+        let mut hasher = Fnv1a::default();
+        canonical.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    /// the leading categories the two schemas share, with the same name, requirement,
+    /// and keyword ids in the same order.
+    pub fn common_prefix(&self, other: &Schema) -> Vec<Category> {
+        let ids = |kws: &[Keyword]| kws.iter().map(|kw| kw.id.clone()).collect::<Vec<String>>();
+        self.categories
+            .iter()
+            .zip(&other.categories)
+            .take_while(|((a, akws), (b, bkws))| {
+                a.name == b.name
+                    && a.requirement.canonical() == b.requirement.canonical()
+                    && ids(akws) == ids(bkws)
+            })
+            .map(|((cat, _), _)| cat.clone())
+            .collect()
+    }
+
+    /// ids of the named category's keywords that start with what has been typed so far,
+    /// ignoring case when the schema does. Draft keywords are left out since they can't be chosen.
+    pub fn completions(&self, category: &str, typed: &str) -> Vec<&str> {
+        let lower = typed.to_lowercase();
+        let matches = |id: &str| {
+            if self.case_insensitive {
+                id.to_lowercase().starts_with(&lower)
+            } else {
+                id.starts_with(typed)
+            }
+        };
+        self.categories
+            .iter()
+            .filter(|(cat, _)| cat.name == category)
+            .flat_map(|(_, kws)| kws)
+            .filter(|kw| !kw.draft)
+            .map(|kw| kw.id.as_str())
+            .filter(|id| matches(id))
+            .collect()
+    }
+
+    /// every keyword id and name in the schema, sorted without duplicates.
+    pub fn vocabulary(&self) -> BTreeSet<String> {
+        self.all_keywords()
+            .into_iter()
+            .flat_map(|(_, kw)| [kw.id.clone(), kw.name.clone()])
+            .collect()
+    }
+
+    /// pairs of keyword ids, shorter first, where one starts with the other. These can't be
+    /// told apart when tags aren't separated by a delimiter.
+    pub fn prefix_conflicts(&self) -> Vec<(String, String)> {
+        let ids: Vec<&str> = self
+            .all_keywords()
+            .into_iter()
+            .map(|(_, kw)| kw.id.as_str())
+            .collect();
+        let mut conflicts: Vec<(String, String)> = vec![];
+        for (i, a) in ids.iter().enumerate() {
+            for b in &ids[i + 1..] {
+                let (short, long) = if a.len() <= b.len() { (a, b) } else { (b, a) };
+                let pair = (short.to_string(), long.to_string());
+                if long.starts_with(short) && !conflicts.contains(&pair) {
+                    conflicts.push(pair);
+                }
+            }
+        }
+        conflicts
+    }
+
+    /// categories that must have tags first, with exact counts before open ended ones,
+    /// then optional categories. Ties keep their schema order.
+    pub fn categories_by_priority(&self) -> Vec<&Category> {
+        let mut cats: Vec<&Category> = self.categories.iter().map(|(cat, _)| cat).collect();
+        cats.sort_by_key(|cat| {
+            let req = cat.requirement.canonical();
+            let exact = req.max_count() == Some(req.min_count());
+            match req.min_count() {
+                0 => 2,
+                _ if exact => 0,
+                _ => 1,
+            }
+        });
+        cats
+    }
+
+    /// the segment after the salt that carries the schema version, if there is one.
+    pub fn version_segment(&self) -> Option<String> {
+        self.version.map(|n| format!("v{n}"))
+    }
+
+    /// the tag as it's written in a filename, with delimiters doubled when they're escaped.
+    pub fn escape_tag<'a>(&self, tag: &'a str) -> Cow<'a, str> {
+        if self.escape_delim && self.delim.is_in(tag) {
+            let d = self.delim.to_string();
+            Cow::Owned(tag.replace(&d, &d.repeat(2)))
+        } else {
+            Cow::Borrowed(tag)
+        }
+    }
+
+    /// every distinct separator that can appear in a generated filename.
+    pub fn delimiters(&self) -> Vec<String> {
+        let mut delims = vec![self.delim.to_string()];
+        if self.labeled {
+            delims.push(LABEL_DELIM.to_string());
+        }
+        for (cat, _) in &self.categories {
+            if let Some(intra) = &cat.intra_delim {
+                delims.push(intra.to_string());
+            }
+        }
+        // keep the first occurrence of each so the schema's delimiter stays first
+        let mut seen = std::collections::HashSet::new();
+        delims.retain(|d| seen.insert(d.clone()));
+        delims
+    }
+
+    /// checks that the state could generate a filename: every category meets its
+    /// requirement and the tag cap, and there's at least one tag when the schema requires one.
+    pub fn validate_state(&self, state: &State) -> std::result::Result<(), GenerateFilenameError> {
+        let mut any = false;
+        for (cat, kws) in state {
+            let got = kws.iter().filter(|(_, tf)| *tf).count();
+            if !cat.requirement.satisfied_by(got) {
+                return Err(GenerateFilenameError::RequirementMismatch {
+                    category: cat.clone(),
+                    expected: cat.requirement,
+                    got,
+                });
+            }
+            if let Some(max) = self.max_tags_per_category.filter(|max| got > *max) {
+                return Err(GenerateFilenameError::TooManyTags {
+                    category: cat.name.clone(),
+                    max,
+                    got,
+                });
+            }
+            any |= got > 0;
+        }
+        if self.require_any_tag && !any {
+            return Err(GenerateFilenameError::NoTags);
+        }
+        Ok(())
+    }
+
+    /// whether each category in the state currently satisfies its requirement.
+    pub fn satisfied(&self, state: &State) -> Vec<bool> {
+        state
+            .iter()
+            .map(|(cat, kws)| {
+                let selected = kws.iter().filter(|(_, tf)| *tf).count();
+                cat.requirement.satisfied_by(selected)
+            })
+            .collect()
+    }
+}
+
+/// separates the tags in a filename. Single character delimiters are kept as a
+/// `char` so splitting filenames doesn't have to search for a substring.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum Delim {
+    Char(char),
+    Str(String),
+}
+
+impl From<&str> for Delim {
+    fn from(s: &str) -> Self {
+        let mut chars = s.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) => Delim::Char(c),
+            _ => Delim::Str(s.to_string()),
+        }
+    }
+}
+
+impl fmt::Display for Delim {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Delim::Char(c) => write!(f, "{c}"),
+            Delim::Str(s) => write!(f, "{s}"),
+        }
+    }
+}
+
+impl Delim {
+    /// length in bytes
+    pub fn len(&self) -> usize {
+        match self {
+            Delim::Char(c) => c.len_utf8(),
+            Delim::Str(s) => s.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn push_to(&self, s: &mut String) {
+        match self {
+            Delim::Char(c) => s.push(*c),
+            Delim::Str(d) => s.push_str(d),
+        }
+    }
+
+    pub fn split<'a>(&'a self, s: &'a str) -> Split<'a> {
+        match self {
+            Delim::Char(c) => Split::Char(s.split(*c)),
+            Delim::Str(d) => Split::Str(s.split(d.as_str())),
+        }
+    }
+
+    /// splits on single delimiters, treating each doubled delimiter as a literal one.
+    /// a run of three splits after the literal delimiter.
+    pub fn split_escaped<'a>(&self, s: &'a str) -> Vec<Cow<'a, str>> {
+        let d = self.to_string();
+        let mut segments = vec![];
+        let (mut start, mut owned): (usize, Option<String>) = (0, None);
+        let mut rest = s;
+        while let Some(i) = rest.find(d.as_str()) {
+            let at = s.len() - rest.len() + i;
+            rest = &rest[i + d.len()..];
+            if let Some(after) = rest.strip_prefix(d.as_str()) {
+                let seg = owned.get_or_insert_with(String::new);
+                seg.push_str(&s[start..at]);
+                seg.push_str(&d);
+                rest = after;
+                start = s.len() - rest.len();
+                continue;
+            }
+            segments.push(match owned.take() {
+                Some(mut seg) => {
+                    seg.push_str(&s[start..at]);
+                    Cow::Owned(seg)
+                }
+                None => Cow::Borrowed(&s[start..at]),
+            });
+            start = s.len() - rest.len();
+        }
+        segments.push(match owned {
+            Some(mut seg) => {
+                seg.push_str(&s[start..]);
+                Cow::Owned(seg)
+            }
+            None => Cow::Borrowed(&s[start..]),
+        });
+        segments
+    }
+
+    pub fn split_once<'a>(&self, s: &'a str) -> Option<(&'a str, &'a str)> {
+        match self {
+            Delim::Char(c) => s.split_once(*c),
+            Delim::Str(d) => s.split_once(d.as_str()),
+        }
+    }
+
+    pub fn rsplit_once<'a>(&self, s: &'a str) -> Option<(&'a str, &'a str)> {
+        match self {
+            Delim::Char(c) => s.rsplit_once(*c),
+            Delim::Str(d) => s.rsplit_once(d.as_str()),
+        }
+    }
+
+    pub fn is_in(&self, s: &str) -> bool {
+        match self {
+            Delim::Char(c) => s.contains(*c),
+            Delim::Str(d) => s.contains(d.as_str()),
+        }
+    }
+
+    pub fn starts(&self, s: &str) -> bool {
+        match self {
+            Delim::Char(c) => s.starts_with(*c),
+            Delim::Str(d) => s.starts_with(d.as_str()),
+        }
+    }
+
+    pub fn ends(&self, s: &str) -> bool {
+        match self {
+            Delim::Char(c) => s.ends_with(*c),
+            Delim::Str(d) => s.ends_with(d.as_str()),
+        }
+    }
+}
+
+/// iterator over the pieces of a string separated by a `Delim`.
+pub enum Split<'a> {
+    Char(std::str::Split<'a, char>),
+    Str(std::str::Split<'a, &'a str>),
+}
+
+impl<'a> Iterator for Split<'a> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<&'a str> {
+        match self {
+            Split::Char(it) => it.next(),
+            Split::Str(it) => it.next(),
+        }
+    }
+}
+
 #[derive(Clone, Debug, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct Category {
     pub name: String,
     pub requirement: Requirement,
+    /// accepts any tag instead of a fixed set of keywords
+    pub freeform: bool,
+    /// separates this category's tags from each other instead of the schema's delimiter
+    pub intra_delim: Option<Delim>,
+    /// its tags can also come after later categories' tags
+    pub repeatable: bool,
 }
 
-#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+impl Category {
+    /// fewest and most tags the category can have, for controls like sliders.
+    /// There's no most when the requirement has no upper limit.
+    pub fn count_bounds(&self) -> (usize, Option<usize>) {
+        (self.requirement.min_count(), self.requirement.max_count())
+    }
+
+    /// how many distinct sets of tags satisfy the requirement when choosing from this
+    /// many keywords. Saturates at `u128::MAX`, which is also the count for freeform categories.
+    pub fn selection_count(&self, available: usize) -> u128 {
+        if self.freeform {
+            return u128::MAX;
+        }
+        let lo = self.requirement.min_count();
+        let hi = self
+            .requirement
+            .max_count()
+            .unwrap_or(available)
+            .min(available);
+        (lo..=hi)
+            .map(|k| binomial(available, k))
+            .fold(0, u128::saturating_add)
+    }
+}
+
+/// n choose k, saturating at `u128::MAX`
+fn binomial(n: usize, k: usize) -> u128 {
+    if k > n {
+        return 0;
+    }
+    let k = k.min(n - k);
+    let mut acc: u128 = 1;
+    for i in 0..k {
+        // exact at every step since acc is C(n, i)
+        match acc.checked_mul((n - i) as u128) {
+            Some(x) => acc = x / (i as u128 + 1),
+            None => return u128::MAX,
+        }
+    }
+    acc
+}
+
+/// requirements are ordered by variant in declaration order, then by their nat.
+/// use `is_stricter_than` to compare which tag counts they accept.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum Requirement {
     Exactly(u8),
     AtLeast(u8),
     AtMost(u8),
 }
 
+impl Requirement {
+    pub fn exactly(n: u8) -> Requirement {
+        Self::Exactly(n)
+    }
+
+    pub fn at_least(n: u8) -> Requirement {
+        Self::AtLeast(n)
+    }
+
+    pub fn at_most(n: u8) -> Requirement {
+        Self::AtMost(n)
+    }
+
+    /// errors when a category with this many keywords could never satisfy the requirement.
+    pub fn check_against(
+        &self,
+        available: usize,
+    ) -> std::result::Result<(), InfeasibleRequirement> {
+        if self.feasible(available) {
+            Ok(())
+        } else {
+            Err(InfeasibleRequirement {
+                requirement: *self,
+                available,
+            })
+        }
+    }
+
+    /// fewest tags that satisfy this requirement
+    pub fn min_count(&self) -> usize {
+        match *self {
+            Self::Exactly(n) | Self::AtLeast(n) => n as usize,
+            Self::AtMost(_) => 0,
+        }
+    }
+
+    /// most tags that satisfy this requirement, if there is a limit
+    pub fn max_count(&self) -> Option<usize> {
+        match *self {
+            Self::Exactly(n) | Self::AtMost(n) => Some(n as usize),
+            Self::AtLeast(_) => None,
+        }
+    }
+
+    /// true when every tag count this accepts is also accepted by the other, but not vice versa.
+    pub fn is_stricter_than(&self, other: &Requirement) -> bool {
+        let within_max = match (self.max_count(), other.max_count()) {
+            (_, None) => true,
+            (None, Some(_)) => false,
+            (Some(a), Some(b)) => a <= b,
+        };
+        let same = self.min_count() == other.min_count() && self.max_count() == other.max_count();
+        other.min_count() <= self.min_count() && within_max && !same
+    }
+
+    /// a single representative for requirements that accept the same tag counts,
+    /// so equivalent requirements compare equal.
+    pub fn canonical(&self) -> Requirement {
+        match *self {
+            Self::AtMost(0) => Self::Exactly(0),
+            x => x,
+        }
+    }
+
+    pub fn satisfied_by(&self, count: usize) -> bool {
+        match *self {
+            Self::Exactly(n) => count == n as usize,
+            Self::AtLeast(n) => count >= n as usize,
+            Self::AtMost(n) => count <= n as usize,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct InfeasibleRequirement {
+    pub requirement: Requirement,
+    pub available: usize,
+}
+
+impl fmt::Display for InfeasibleRequirement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "A requirement of {} can't be met with only {} keywords.",
+            self.requirement, self.available
+        )
+    }
+}
+
+impl StdError for InfeasibleRequirement {}
+
+/// behavior shared by every kind of requirement a category can place on its tags.
+pub trait RequirementKind {
+    /// the function name used to construct this requirement in a schema
+    fn name(&self) -> &str;
+    fn satisfied_by(&self, count: usize) -> bool;
+    /// whether the requirement can ever be satisfied with this many keywords to choose from
+    fn feasible(&self, available: usize) -> bool;
+}
+
+/// names of every function the typechecker understands.
+pub const BUILTINS: &[&str] = &[
+    "schema",
+    "category",
+    "freeform",
+    "repeatable",
+    "draft",
+    "exactly",
+    "at_least",
+    "at_most",
+    "min",
+    "max",
+];
+
+pub type RequirementConstructor = fn(u8) -> Requirement;
+
+/// every requirement the typechecker knows how to construct, keyed by function name.
+pub const REQUIREMENT_KINDS: &[(&str, RequirementConstructor)] = &[
+    ("exactly", Requirement::Exactly),
+    ("at_least", Requirement::AtLeast),
+    ("at_most", Requirement::AtMost),
+];
+
+/// other names for requirements, paired with the name in `REQUIREMENT_KINDS` they stand for.
+pub const REQUIREMENT_ALIASES: &[(&str, &str)] = &[("min", "at_least"), ("max", "at_most")];
+
+impl RequirementKind for Requirement {
+    fn name(&self) -> &str {
+        match self {
+            Self::Exactly(_) => "exactly",
+            Self::AtLeast(_) => "at_least",
+            Self::AtMost(_) => "at_most",
+        }
+    }
+
+    fn satisfied_by(&self, count: usize) -> bool {
+        Requirement::satisfied_by(self, count)
+    }
+
+    fn feasible(&self, available: usize) -> bool {
+        self.min_count() <= available
+    }
+}
+
 impl fmt::Display for Requirement {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
@@ -35,15 +927,82 @@ impl fmt::Display for Requirement {
 }
 
 #[derive(Clone, Debug, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct Keyword {
     pub name: String,
     pub id: String,
+    /// shown to users but never part of a filename
+    pub description: Option<String>,
+    /// still parses so older filenames can be read, but can't be chosen for new ones
+    pub draft: bool,
+}
+
+/// written the same way as in a schema file: the name, followed by the id when they differ
+impl fmt::Display for Keyword {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.name == self.id {
+            write!(f, "{}", self.name)
+        } else {
+            write!(f, "{}/{}", self.name, self.id)
+        }
+    }
+}
+
+/// generates ids that are safe in the test schema's filenames: lowercase ascii and digits
+#[cfg(test)]
+impl quickcheck::Arbitrary for Keyword {
+    fn arbitrary(g: &mut quickcheck::Gen) -> Self {
+        const CHARS: &[char] = &[
+            'a', 'b', 'c', 'd', 'e', 'f', 'g', 'h', 'i', 'j', 'k', 'l', 'm', 'n', 'o', 'p', 'q',
+            'r', 's', 't', 'u', 'v', 'w', 'x', 'y', 'z', '0', '1', '2', '3', '4', '5', '6', '7',
+            '8', '9',
+        ];
+        let word = |g: &mut quickcheck::Gen, max: usize| -> String {
+            let len = 1 + usize::arbitrary(g) % max;
+            (0..len).map(|_| *g.choose(CHARS).unwrap()).collect()
+        };
+        let name = word(g, 10);
+        let id = if bool::arbitrary(g) {
+            name.clone()
+        } else {
+            word(g, 4)
+        };
+        Keyword {
+            name,
+            id,
+            description: None,
+            draft: false,
+        }
+    }
+
+    /// shrinks toward shorter ids, never to an empty one
+    fn shrink(&self) -> Box<dyn Iterator<Item = Self>> {
+        let kw = self.clone();
+        Box::new((1..self.id.chars().count()).rev().map(move |n| Keyword {
+            id: kw.id.chars().take(n).collect(),
+            ..kw.clone()
+        }))
+    }
 }
 
 #[derive(Clone, Debug, Eq, Hash, PartialEq)]
 pub enum SchemaParseError {
     MustStartWithSchemaConstructor,
     UnexpectedInput(String),
+    UnclosedDelimiter {
+        open: char,
+        at: usize,
+    },
+    LeadingZero(String),
+    /// a well formed number too large for a nat
+    NatOverflow {
+        value: String,
+        max: u32,
+    },
+    /// a backslash followed by a character that can't be escaped
+    InvalidEscape(String),
+    /// content after a complete top level schema
+    TrailingExpression(String),
 }
 
 impl fmt::Display for SchemaParseError {
@@ -53,6 +1012,17 @@ impl fmt::Display for SchemaParseError {
                 write!(f, "Expected \"schema\" constructor")
             }
             Self::UnexpectedInput(input) => write!(f, "Unexpected input: {input}"),
+            Self::UnclosedDelimiter { open, at } => {
+                write!(f, "Unclosed '{open}' starting at offset {at}")
+            }
+            Self::LeadingZero(digits) => write!(f, "Number {digits} has a leading zero"),
+            Self::NatOverflow { value, max } => {
+                write!(f, "Number {value} is larger than the maximum of {max}")
+            }
+            Self::InvalidEscape(seq) => write!(f, "Unknown escape sequence {seq}"),
+            Self::TrailingExpression(rest) => {
+                write!(f, "Unexpected content after the end of the schema: {rest}")
+            }
         }
     }
 }
@@ -62,9 +1032,23 @@ impl StdError for SchemaParseError {}
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub enum SchemaTypeCheckError {
     HeterogeneousList(Vec<Type>),
-    TypeMismatch { expected: Type, got: Type },
-    UnknownFunction { name: String, arg_types: Vec<Type> },
+    TypeMismatch {
+        expected: Type,
+        got: Type,
+    },
+    UnknownFunction {
+        name: String,
+        arg_types: Vec<Type>,
+    },
     ExpectedTopLevelSchema,
+    DuplicateKeywordId {
+        category: String,
+        id: String,
+    },
+    /// a keyword id that's the empty placeholder can't be told apart from an empty category
+    KeywordEqualsEmpty {
+        keyword: String,
+    },
 }
 
 impl fmt::Display for SchemaTypeCheckError {
@@ -86,21 +1070,475 @@ impl fmt::Display for SchemaTypeCheckError {
                 display_types(arg_types)
             ),
             Self::ExpectedTopLevelSchema => write!(f, "The top level value must be a schema."),
+            Self::DuplicateKeywordId { category, id } => write!(
+                f,
+                "Category \"{category}\" has different keywords with the same id \"{id}\"."
+            ),
+            Self::KeywordEqualsEmpty { keyword } => write!(
+                f,
+                "Keyword id \"{keyword}\" is the same as the empty placeholder."
+            ),
         }
     }
 }
 
 impl StdError for SchemaTypeCheckError {}
 
+/// opt-in relaxations of the checks made by `Schema::validate_with`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ValidateOptions {
+    /// allow categories like `at_most 0` that can never have a tag
+    pub allow_empty_category: bool,
+    /// allow categories whose keyword ids are all keyword ids of another category
+    pub allow_overlapping_categories: bool,
+}
+
+/// builds a schema in code, checking each category as it's added so mistakes
+/// are reported by the call that made them.
+#[derive(Clone, Debug)]
+pub struct SchemaBuilder {
+    schema: Schema,
+}
+
+impl SchemaBuilder {
+    /// adds a category, erroring if its requirement can't be met by its keywords
+    /// or a category already has its name.
+    pub fn category(
+        mut self,
+        category: Category,
+        keywords: Vec<Keyword>,
+    ) -> std::result::Result<Self, SchemaError> {
+        if let (false, Err(e)) = (
+            category.freeform,
+            category
+                .requirement
+                .check_against(keywords.iter().filter(|kw| !kw.draft).count()),
+        ) {
+            return Err(SchemaError::Infeasible {
+                category: category.name,
+                requirement: e.requirement,
+                available: e.available,
+            });
+        }
+        if self
+            .schema
+            .categories
+            .iter()
+            .any(|(other, _)| other.name == category.name)
+        {
+            return Err(SchemaError::DuplicateCategory(category.name));
+        }
+        self.schema.categories.push((category, keywords));
+        Ok(self)
+    }
+
+    /// the schema, if it passes every check in `Schema::validate`.
+    pub fn build(self) -> std::result::Result<Schema, Vec<SchemaError>> {
+        self.schema.validate()?;
+        Ok(self.schema)
+    }
+}
+
+/// 64-bit FNV-1a, which is stable across platforms and compiler versions.
+/// Used for salt checksums and schema fingerprints.
+pub(crate) struct Fnv1a(u64);
+
+impl Default for Fnv1a {
+    fn default() -> Self {
+        Fnv1a(0xcbf29ce484222325)
+    }
+}
+
+impl Hasher for Fnv1a {
+    fn write(&mut self, bytes: &[u8]) {
+        for b in bytes {
+            self.0 = (self.0 ^ *b as u64).wrapping_mul(0x100000001b3);
+        }
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+/// any problem with a schema, from its source text through checks on the compiled schema.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum SchemaError {
+    Parse(SchemaParseError),
+    Typecheck(SchemaTypeCheckError),
+    /// the category can't be satisfied by the keywords it has to choose from
+    Infeasible {
+        category: String,
+        requirement: Requirement,
+        available: usize,
+    },
+    DuplicateCategory(String),
+    /// the category's requirement never allows a tag
+    EmptyCategory(String),
+    /// the keyword id would split into more than one tag
+    DelimiterInKeyword {
+        category: String,
+        id: String,
+    },
+    /// the keyword id can't be told apart from an empty category
+    EmptyKeyword {
+        category: String,
+        id: String,
+    },
+    /// the empty placeholder could be mistaken for a salt
+    PlaceholderIsSalt(String),
+    /// the category name can't be written in a labeled filename
+    IllegalCategoryName(String),
+    /// every keyword id of one category is also a keyword id of the other
+    OverlappingCategories {
+        subset: String,
+        superset: String,
+    },
+    /// one keyword id starts with another. Only a lint: `Schema::validate` allows it.
+    PrefixConflict {
+        shorter: String,
+        longer: String,
+    },
+}
+
+impl fmt::Display for SchemaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Parse(e) => write!(f, "{e}"),
+            Self::Typecheck(e) => write!(f, "{e}"),
+            Self::Infeasible {
+                category,
+                requirement,
+                available,
+            } => write!(
+                f,
+                "Category \"{category}\" requires {requirement} tags but only has {available} keywords."
+            ),
+            Self::DuplicateCategory(name) => {
+                write!(f, "There is more than one category named \"{name}\".")
+            }
+            Self::EmptyCategory(name) => {
+                write!(f, "Category \"{name}\" can never have any tags.")
+            }
+            Self::DelimiterInKeyword { category, id } => write!(
+                f,
+                "Keyword id \"{id}\" in category \"{category}\" contains the delimiter."
+            ),
+            Self::EmptyKeyword { category, id } => write!(
+                f,
+                "Keyword id \"{id}\" in category \"{category}\" is empty or the empty placeholder."
+            ),
+            Self::PlaceholderIsSalt(empty) => {
+                write!(f, "The empty placeholder \"{empty}\" is also a valid salt.")
+            }
+            Self::OverlappingCategories { subset, superset } => write!(
+                f,
+                "Every keyword id in category \"{subset}\" is also in category \"{superset}\"."
+            ),
+            Self::IllegalCategoryName(name) => write!(
+                f,
+                "Category name \"{name}\" is empty or contains a delimiter or path separator."
+            ),
+            Self::PrefixConflict { shorter, longer } => write!(
+                f,
+                "Keyword id \"{shorter}\" is a prefix of keyword id \"{longer}\"."
+            ),
+        }
+    }
+}
+
+impl StdError for SchemaError {}
+
+impl From<SchemaParseError> for SchemaError {
+    fn from(e: SchemaParseError) -> Self {
+        SchemaError::Parse(e)
+    }
+}
+
+impl From<SchemaTypeCheckError> for SchemaError {
+    fn from(e: SchemaTypeCheckError) -> Self {
+        SchemaError::Typecheck(e)
+    }
+}
+
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum ExprU {
-    KeywordU { name: String, id: String },
+    KeywordU {
+        name: String,
+        id: String,
+        description: Option<String>,
+    },
     StringU(String),
-    FnU { name: String, args: Vec<ExprU> },
+    FnU {
+        name: String,
+        args: Vec<ExprU>,
+    },
     ListU(Vec<ExprU>),
     NatU(u8),
 }
 
+/// every string literal, keyword name, and keyword description in source order
+/// so display text can be extracted for translation. Keyword ids are never shown
+/// to users, so they are skipped.
+pub fn collect_strings(expr: &ExprU) -> Vec<&str> {
+    fn go<'a>(expr: &'a ExprU, acc: &mut Vec<&'a str>) {
+        match expr {
+            ExprU::KeywordU {
+                name, description, ..
+            } => {
+                acc.push(name);
+                acc.extend(description.as_deref());
+            }
+            ExprU::StringU(s) => acc.push(s),
+            ExprU::FnU { args: xs, .. } | ExprU::ListU(xs) => {
+                for x in xs {
+                    go(x, acc);
+                }
+            }
+            ExprU::NatU(_) => (),
+        }
+    }
+    let mut acc = vec![];
+    go(expr, &mut acc);
+    acc
+}
+
+/// the untyped expression that typechecks to this schema. Settings the schema
+/// language can't express, like the salt and version, are left out.
+pub fn schema_to_expr(schema: &Schema) -> ExprU {
+    let call = |name: &str, args: Vec<ExprU>| ExprU::FnU {
+        name: name.to_string(),
+        args,
+    };
+    let categories = schema
+        .categories
+        .iter()
+        .map(|(cat, kws)| {
+            let (Requirement::Exactly(n) | Requirement::AtLeast(n) | Requirement::AtMost(n)) =
+                cat.requirement;
+            let mut args = vec![
+                ExprU::StringU(cat.name.clone()),
+                call(cat.requirement.name(), vec![ExprU::NatU(n)]),
+            ];
+            args.extend(
+                cat.intra_delim
+                    .as_ref()
+                    .map(|intra| ExprU::StringU(intra.to_string())),
+            );
+            let expr = if cat.freeform {
+                call("freeform", args)
+            } else {
+                args.push(ExprU::ListU(
+                    kws.iter()
+                        .map(|kw| {
+                            let expr = ExprU::KeywordU {
+                                name: kw.name.clone(),
+                                id: kw.id.clone(),
+                                description: kw.description.clone(),
+                            };
+                            if kw.draft {
+                                call("draft", vec![expr])
+                            } else {
+                                expr
+                            }
+                        })
+                        .collect(),
+                ));
+                call("category", args)
+            };
+            if cat.repeatable {
+                call("repeatable", vec![expr])
+            } else {
+                expr
+            }
+        })
+        .collect();
+    call(
+        "schema",
+        vec![
+            ExprU::StringU(schema.delim.to_string()),
+            ExprU::StringU(schema.empty.clone()),
+            ExprU::ListU(categories),
+        ],
+    )
+}
+
+/// parses, typechecks, and validates schema source text, returning every problem found.
+/// parsing and typechecking stop at their first error.
+pub fn validate_schema_source(contents: &str) -> std::result::Result<(), Vec<SchemaError>> {
+    let parsed = parse::parse(contents).map_err(|e| vec![e.into()])?;
+    let schema = typecheck::typecheck(parsed).map_err(|e| vec![e.into()])?;
+    schema.validate()
+}
+
+/// how much a problem with a schema matters.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize),
+    serde(rename_all = "lowercase")
+)]
+pub enum Severity {
+    /// `Schema::validate` fails on it, even if `ValidateOptions` can relax the check
+    Error,
+    /// the schema still validates, but is likely a mistake
+    Warning,
+}
+
+/// a problem with schema source text in a form tools can consume.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Diagnostic {
+    pub severity: Severity,
+    /// stable identifier for the kind of problem
+    pub code: &'static str,
+    pub message: String,
+    /// byte range of the source text the problem is in, when it's known
+    pub span: Option<Range<usize>>,
+}
+
+impl SchemaError {
+    /// every error `Schema::validate` returns fails it, including the ones
+    /// `ValidateOptions` can allow. Lints it never returns are warnings.
+    pub fn severity(&self) -> Severity {
+        match self {
+            Self::PrefixConflict { .. } => Severity::Warning,
+            _ => Severity::Error,
+        }
+    }
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::Parse(_) => "parse",
+            Self::Typecheck(_) => "typecheck",
+            Self::Infeasible { .. } => "infeasible",
+            Self::DuplicateCategory(_) => "duplicate_category",
+            Self::EmptyCategory(_) => "empty_category",
+            Self::DelimiterInKeyword { .. } => "delimiter_in_keyword",
+            Self::EmptyKeyword { .. } => "empty_keyword",
+            Self::PlaceholderIsSalt(_) => "placeholder_is_salt",
+            Self::OverlappingCategories { .. } => "overlapping_categories",
+            Self::IllegalCategoryName(_) => "illegal_category_name",
+            Self::PrefixConflict { .. } => "prefix_conflict",
+        }
+    }
+
+    /// where in the source the error is. Only parse errors know their position.
+    pub fn span(&self, src: &str) -> Option<Range<usize>> {
+        // the rest of the input from where parsing stopped
+        let rest = |rest: &str, src: &str| {
+            src.ends_with(rest)
+                .then(|| src.len() - rest.len()..src.len())
+        };
+        match self {
+            Self::Parse(SchemaParseError::UnclosedDelimiter { at, .. }) => Some(*at..*at + 1),
+            Self::Parse(SchemaParseError::UnexpectedInput(input)) => rest(input, src),
+            Self::Parse(SchemaParseError::TrailingExpression(input)) => rest(input, src.trim_end()),
+            _ => None,
+        }
+    }
+}
+
+/// every problem with schema source text as a diagnostic: validation errors first, then
+/// lint warnings for schemas that compile.
+pub fn diagnostics(contents: &str) -> Vec<Diagnostic> {
+    let schema = parse::parse(contents)
+        .map_err(SchemaError::from)
+        .and_then(|parsed| typecheck::typecheck(parsed).map_err(SchemaError::from));
+    let problems = match schema {
+        Err(e) => vec![e],
+        Ok(schema) => {
+            let mut problems = schema.validate().err().unwrap_or_default();
+            // empty and repeated ids are already validation errors
+            problems.extend(
+                schema
+                    .prefix_conflicts()
+                    .into_iter()
+                    .filter(|(shorter, longer)| !shorter.is_empty() && shorter != longer)
+                    .map(|(shorter, longer)| SchemaError::PrefixConflict { shorter, longer }),
+            );
+            problems
+        }
+    };
+    problems
+        .iter()
+        .map(|e| Diagnostic {
+            severity: e.severity(),
+            code: e.code(),
+            message: e.to_string(),
+            span: e.span(contents),
+        })
+        .collect()
+}
+
+/// `diagnostics` as a JSON array for CI tools.
+#[cfg(feature = "serde")]
+pub fn diagnostics_json(contents: &str) -> String {
+    serde_json::to_string(&diagnostics(contents)).expect("diagnostics always serialize")
+}
+
+/// guesses a schema from example filenames without their salts. Each position becomes a
+/// category of the values seen there, required when every example has it. Examples of
+/// different lengths make empty categories collapse, so every example still parses.
+pub fn infer_schema(delim: &str, examples: &[&str]) -> Schema {
+    let delim = Delim::from(delim);
+    let rows: Vec<Vec<&str>> = examples.iter().map(|x| delim.split(x).collect()).collect();
+    let width = rows.iter().map(Vec::len).max().unwrap_or(0);
+    let categories = (0..width)
+        .map(|i| {
+            let mut kws: Vec<Keyword> = vec![];
+            for value in rows.iter().filter_map(|row| row.get(i)) {
+                if !kws.iter().any(|kw| kw.id == *value) {
+                    kws.push(Keyword {
+                        name: value.to_string(),
+                        id: value.to_string(),
+                        description: None,
+                        draft: false,
+                    });
+                }
+            }
+            let requirement = if rows.iter().all(|row| row.len() > i) {
+                Requirement::Exactly(1)
+            } else {
+                Requirement::AtLeast(0)
+            };
+            let cat = Category {
+                name: format!("Category{}", i + 1),
+                requirement,
+                freeform: false,
+                intra_delim: None,
+                repeatable: false,
+            };
+            (cat, kws)
+        })
+        .collect();
+    let ragged = rows.iter().any(|row| row.len() != width);
+    Schema {
+        delim,
+        empty: "_".to_string(),
+        salt: SaltSpec::default(),
+        labeled: false,
+        escape_delim: false,
+        empty_mode: if ragged {
+            EmptyMode::Collapse
+        } else {
+            EmptyMode::Placeholder
+        },
+        require_any_tag: false,
+        max_tags_per_category: None,
+        case_insensitive: false,
+        version: None,
+        categories,
+    }
+}
+
+/// parses and typechecks schema source text
+pub fn compile_schema(input: &str) -> Result<Schema> {
+    let parsed = parse::parse(input)?;
+    let schema = typecheck::typecheck(parsed)?;
+    Ok(schema)
+}
+
 fn display_types(types: &[Type]) -> String {
     let mut x = String::new();
     for t in types {
@@ -110,3 +1548,958 @@ fn display_types(types: &[Type]) -> String {
     x.pop();
     x
 }
+
+#[cfg(test)]
+pub(crate) const TEST_SCHEMA: &str = r#"schema "-" "_" [ category "Media" (exactly 1) ['art', 'photo'/'ph', 'video'/'v'], category "People" (at_least 0) ['nate']]"#;
+
+#[test]
+fn completion() {
+    let schema = compile_schema(TEST_SCHEMA).unwrap();
+    let mut state = crate::app::to_empty_state(&schema);
+    assert_eq!(schema.satisfied(&state), vec![false, true]);
+    assert_eq!(schema.completion(&state), 0.5);
+
+    state[0].1[1].1 = true;
+    assert_eq!(schema.satisfied(&state), vec![true, true]);
+    assert_eq!(schema.completion(&state), 1.0);
+}
+
+#[test]
+fn length_bounds() {
+    use crate::filename::generate_with_salt;
+
+    let schema = compile_schema(
+        r#"schema "--" "_" [ category "Media" (exactly 1) ['art', 'photo'/'ph', 'video'/'v'], category "People" (at_least 0) ['nate', 'kim'], category "Place" (at_most 2) ['home', 'x', 'park']]"#,
+    )
+    .unwrap();
+
+    // brute force every selection that satisfies the schema
+    let mut states = vec![crate::app::to_empty_state(&schema)];
+    for i in 0..schema.categories.len() {
+        states = states
+            .into_iter()
+            .flat_map(|state| {
+                let n = state[i].1.len();
+                (0..1u32 << n).map(move |bits| {
+                    let mut state = state.clone();
+                    for (j, kw) in state[i].1.iter_mut().enumerate() {
+                        kw.1 = bits & (1 << j) != 0;
+                    }
+                    state
+                })
+            })
+            .collect();
+    }
+    let lens: Vec<usize> = states
+        .iter()
+        .filter_map(|state| generate_with_salt(&schema, state, "ABC123").ok())
+        .map(|name| name.len())
+        .collect();
+
+    let expected = (*lens.iter().min().unwrap(), *lens.iter().max().unwrap());
+    assert_eq!(schema.length_bounds(), expected);
+
+    let mut schema = schema;
+    schema.labeled = true;
+    schema.version = Some(12);
+    let lens: Vec<usize> = states
+        .iter()
+        .filter_map(|state| generate_with_salt(&schema, state, "ABC123").ok())
+        .map(|name| name.len())
+        .collect();
+    let expected = (*lens.iter().min().unwrap(), *lens.iter().max().unwrap());
+    assert_eq!(schema.length_bounds(), expected);
+}
+
+#[test]
+fn requirement_kinds() {
+    for (name, mk) in REQUIREMENT_KINDS {
+        for n in 0..4 {
+            let req = mk(n);
+            let kind: &dyn RequirementKind = &req;
+            assert_eq!(kind.name(), *name);
+            for count in 0..6 {
+                assert_eq!(kind.satisfied_by(count), req.satisfied_by(count));
+                assert_eq!(
+                    kind.feasible(count),
+                    (0..=count).any(|x| req.satisfied_by(x))
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn keyword_description() {
+    let schema = compile_schema(
+        r#"schema "-" "_" [ category "Media" (exactly 1) ['art', 'photo'/'ph' : "Photographs"]]"#,
+    )
+    .unwrap();
+    let kws = &schema.categories[0].1;
+    assert_eq!(kws[0].description, None);
+    assert_eq!(kws[1].description.as_deref(), Some("Photographs"));
+
+    // descriptions never reach filenames
+    let mut state = crate::app::to_empty_state(&schema);
+    state[0].1[1].1 = true;
+    assert_eq!(
+        crate::filename::generate(&schema, &state),
+        Ok("ph".to_string())
+    );
+    assert_eq!(schema.parse("ABC123-ph"), Ok(state));
+}
+
+#[test]
+fn requirement_ordering() {
+    use Requirement::*;
+
+    assert!(Exactly(1) < AtLeast(1));
+    assert!(AtLeast(1) < AtMost(2));
+    assert!(Exactly(1) < Exactly(2));
+    let mut reqs = vec![AtMost(2), AtLeast(1), Exactly(2), Exactly(1)];
+    reqs.sort();
+    assert_eq!(reqs, vec![Exactly(1), Exactly(2), AtLeast(1), AtMost(2)]);
+
+    assert!(Exactly(1).is_stricter_than(&AtLeast(1)));
+    assert!(Exactly(1).is_stricter_than(&AtMost(2)));
+    assert!(!AtLeast(1).is_stricter_than(&Exactly(1)));
+    assert!(!AtMost(2).is_stricter_than(&Exactly(1)));
+    assert!(!AtLeast(1).is_stricter_than(&AtMost(2)));
+    assert!(!AtMost(2).is_stricter_than(&AtLeast(1)));
+    assert!(!Exactly(1).is_stricter_than(&Exactly(1)));
+    // the same set of counts written two ways
+    assert!(!Exactly(0).is_stricter_than(&AtMost(0)));
+}
+
+#[test]
+fn computed_length() {
+    use crate::filename::generate_with_salt;
+
+    let mut schema = compile_schema(TEST_SCHEMA).unwrap();
+    let mut state = crate::app::to_empty_state(&schema);
+    assert!(schema.computed_length(&state).is_err());
+    state[0].1[1].1 = true;
+    let name = generate_with_salt(&schema, &state, "ABC123").unwrap();
+    assert_eq!(schema.computed_length(&state), Ok(name.len()));
+
+    state[1].1[0].1 = true;
+    schema.labeled = true;
+    schema.version = Some(12);
+    schema.delim = Delim::from("--");
+    let name = generate_with_salt(&schema, &state, "ABC123").unwrap();
+    assert_eq!(schema.computed_length(&state), Ok(name.len()));
+
+    schema.categories[0].0.requirement = Requirement::AtLeast(1);
+    schema.categories[0].0.intra_delim = Some(Delim::from("+"));
+    let mut state = crate::app::to_empty_state(&schema);
+    state[0].1[0].1 = true;
+    state[0].1[2].1 = true;
+    let name = generate_with_salt(&schema, &state, "ABC123").unwrap();
+    assert_eq!(schema.computed_length(&state), Ok(name.len()));
+}
+
+#[test]
+fn all_keywords() {
+    let schema = compile_schema(TEST_SCHEMA).unwrap();
+    let all: Vec<(&str, &str)> = schema
+        .all_keywords()
+        .into_iter()
+        .map(|(cat, kw)| (cat, kw.id.as_str()))
+        .collect();
+    assert_eq!(
+        all,
+        vec![
+            ("Media", "art"),
+            ("Media", "ph"),
+            ("Media", "v"),
+            ("People", "nate"),
+        ]
+    );
+}
+
+#[test]
+fn fingerprint() {
+    let schema = compile_schema(
+        r#"schema "-" "_" [ category "Media" (exactly 1) ['art', 'ph'], category "People" (at_most 0) ['nate'] ]"#,
+    )
+    .unwrap();
+    let reordered = compile_schema(
+        r#"schema "-" "_" [ category "People" (exactly 0) ['nate'], category "Media" (exactly 1) ['art', 'ph'] ]"#,
+    )
+    .unwrap();
+    assert_ne!(schema, reordered);
+    assert_eq!(schema.fingerprint(), reordered.fingerprint());
+    assert_eq!(schema.fingerprint(), schema.clone().fingerprint());
+
+    let mut changed = schema.clone();
+    changed.categories[0].1[1].id = "photo".to_string();
+    assert_ne!(schema.fingerprint(), changed.fingerprint());
+    let mut changed = schema.clone();
+    changed.delim = Delim::from("_");
+    assert_ne!(schema.fingerprint(), changed.fingerprint());
+}
+
+#[test]
+fn max_tags_per_category() {
+    use crate::filename::generate_with_salt;
+
+    let mut schema = compile_schema(
+        r#"schema "-" "_" [ category "Media" (exactly 1) ['art', 'ph'], category "People" (at_least 0) ['nate', 'kim', 'sam'] ]"#,
+    )
+    .unwrap();
+    let mut state = crate::app::to_empty_state(&schema);
+    state[0].1[0].1 = true;
+    for (_, tf) in state[1].1.iter_mut() {
+        *tf = true;
+    }
+    assert_eq!(schema.validate_state(&state), Ok(()));
+
+    schema.max_tags_per_category = Some(2);
+    let too_many = GenerateFilenameError::TooManyTags {
+        category: "People".to_string(),
+        max: 2,
+        got: 3,
+    };
+    assert_eq!(schema.validate_state(&state), Err(too_many.clone()));
+    assert_eq!(generate_with_salt(&schema, &state, "ABC123"), Err(too_many));
+    state[1].1[2].1 = false;
+    assert_eq!(
+        generate_with_salt(&schema, &state, "ABC123"),
+        Ok("ABC123-art-nate-kim".to_string())
+    );
+}
+
+#[test]
+fn require_any_tag() {
+    use crate::filename::generate_with_salt;
+
+    let mut schema = compile_schema(
+        r#"schema "-" "_" [ category "Media" ['art', 'ph'], category "People" ['nate'] ]"#,
+    )
+    .unwrap();
+    let state = crate::app::to_empty_state(&schema);
+    assert_eq!(schema.validate_state(&state), Ok(()));
+    assert_eq!(
+        generate_with_salt(&schema, &state, "ABC123"),
+        Ok("ABC123-_-_".to_string())
+    );
+    assert!(schema.parse("ABC123-_-_").is_ok());
+
+    schema.require_any_tag = true;
+    assert_eq!(
+        schema.validate_state(&state),
+        Err(GenerateFilenameError::NoTags)
+    );
+    assert_eq!(
+        generate_with_salt(&schema, &state, "ABC123"),
+        Err(GenerateFilenameError::NoTags)
+    );
+    assert_eq!(
+        schema.parse("ABC123-_-_"),
+        Err(crate::filename::FilenameParseError::NoTags)
+    );
+    assert!(schema.parse("ABC123-_-nate").is_ok());
+}
+
+#[test]
+fn would_collide_with_delim() {
+    let schema = compile_schema(TEST_SCHEMA).unwrap();
+    assert_eq!(
+        schema.would_collide_with_delim("r"),
+        vec![("Media".to_string(), "art".to_string())]
+    );
+    assert_eq!(schema.would_collide_with_delim("."), vec![]);
+}
+
+#[test]
+fn infer_schema_from_examples() {
+    use crate::filename::generate;
+
+    let examples = ["art-nate-home", "photo-kim", "art-sam-park"];
+    let schema = infer_schema("-", &examples);
+    let reqs: Vec<Requirement> = schema
+        .categories
+        .iter()
+        .map(|(cat, _)| cat.requirement)
+        .collect();
+    assert_eq!(
+        reqs,
+        vec![
+            Requirement::Exactly(1),
+            Requirement::Exactly(1),
+            Requirement::AtLeast(0)
+        ]
+    );
+    assert_eq!(schema.categories[1].1.len(), 3);
+    assert_eq!(schema.validate(), Ok(()));
+    for example in examples {
+        let state = schema.parse(&format!("ABC123-{example}")).unwrap();
+        assert_eq!(generate(&schema, &state).as_deref(), Ok(example));
+    }
+}
+
+#[test]
+fn categories_for_tag() {
+    let schema = compile_schema(
+        r#"schema "-" "_" [ category "Media" ['art', 'ph'], category "Subject" ['art', 'sky'], category "People" ['nate'] ]"#,
+    )
+    .unwrap();
+    let names = |id| -> Vec<&str> {
+        schema
+            .categories_for_tag(id)
+            .into_iter()
+            .map(|cat| cat.name.as_str())
+            .collect()
+    };
+    assert_eq!(names("art"), vec!["Media", "Subject"]);
+    assert_eq!(names("nate"), vec!["People"]);
+    assert_eq!(names("nope"), Vec::<&str>::new());
+}
+
+#[test]
+fn vocabulary() {
+    let schema = compile_schema(TEST_SCHEMA).unwrap();
+    assert_eq!(
+        schema.vocabulary().into_iter().collect::<Vec<String>>(),
+        vec!["art", "nate", "ph", "photo", "v", "video"]
+    );
+}
+
+#[test]
+fn split_escaped() {
+    for delim in [Delim::from("-"), Delim::from("::")] {
+        let d = delim.to_string();
+        let name = ["a", "b--c", "d", "e---f"].join(&d).replace('-', &d);
+        let segments: Vec<String> = delim
+            .split_escaped(&name)
+            .into_iter()
+            .map(|seg| seg.into_owned())
+            .collect();
+        let expected: Vec<String> = ["a", "b-c", "d", "e-", "f"]
+            .iter()
+            .map(|seg| seg.replace('-', &d))
+            .collect();
+        assert_eq!(segments, expected);
+    }
+    assert!(matches!(
+        Delim::from("-").split_escaped("a-b")[0],
+        Cow::Borrowed("a")
+    ));
+
+    let mut schema =
+        compile_schema(r#"schema "-" "_" [ category "Media" ['hand-made', '-art', 'photo-'] ]"#)
+            .unwrap();
+    schema.escape_delim = true;
+    let ids: Vec<String> = schema
+        .validate()
+        .unwrap_err()
+        .into_iter()
+        .filter_map(|e| match e {
+            SchemaError::DelimiterInKeyword { id, .. } => Some(id),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(ids, vec!["-art", "photo-"]);
+}
+
+#[test]
+fn delimiters() {
+    let mut schema = compile_schema(TEST_SCHEMA).unwrap();
+    assert_eq!(schema.delimiters(), vec!["-"]);
+    schema.labeled = true;
+    assert_eq!(schema.delimiters(), vec!["-", "."]);
+    schema.delim = Delim::from(".");
+    assert_eq!(schema.delimiters(), vec!["."]);
+    schema.categories[1].0.intra_delim = Some(Delim::from("+"));
+    assert_eq!(schema.delimiters(), vec![".", "+"]);
+}
+
+#[test]
+fn to_table() {
+    let schema = compile_schema(TEST_SCHEMA).unwrap();
+    assert_eq!(
+        schema.to_table(),
+        "\
+Category | Requirement | Keywords
+---------+-------------+-----------------------
+Media    | exactly 1   | art, photo/ph, video/v
+People   | at least 0  | nate
+"
+    );
+}
+
+#[test]
+fn requirement_canonical() {
+    use Requirement::*;
+    assert_eq!(AtMost(0).canonical(), Exactly(0));
+    assert_eq!(Exactly(0).canonical(), Exactly(0));
+    for req in [Exactly(1), AtLeast(0), AtLeast(2), AtMost(1)] {
+        assert_eq!(req.canonical(), req);
+    }
+    // canonical forms accept exactly the same counts
+    for req in [AtMost(0), Exactly(1), AtLeast(0), AtMost(3)] {
+        for count in 0..5 {
+            assert_eq!(req.satisfied_by(count), req.canonical().satisfied_by(count));
+        }
+    }
+}
+
+#[test]
+fn collect_strings_in_order() {
+    let expr = parse::parse(r#"schema "-" "_" [ category "Media" (exactly 1) ['art', 'photo'/'ph', 'video'/'v'], category "People" (at_least 0) ['nate' : "Me"]]"#).unwrap();
+    assert_eq!(
+        collect_strings(&expr),
+        vec!["-", "_", "Media", "art", "photo", "video", "People", "nate", "Me"]
+    );
+}
+
+#[test]
+fn validate_source() {
+    assert_eq!(validate_schema_source(TEST_SCHEMA), Ok(()));
+    assert_eq!(
+        validate_schema_source(
+            r#"schema "-" "_" [ category "Media" (exactly 3) ['a-b', ''], category "Media" (at_least 0) ['x'] ]"#
+        ),
+        Err(vec![
+            SchemaError::Infeasible {
+                category: "Media".to_string(),
+                requirement: Requirement::Exactly(3),
+                available: 2,
+            },
+            SchemaError::DelimiterInKeyword {
+                category: "Media".to_string(),
+                id: "a-b".to_string(),
+            },
+            SchemaError::EmptyKeyword {
+                category: "Media".to_string(),
+                id: String::new(),
+            },
+            SchemaError::DuplicateCategory("Media".to_string()),
+        ])
+    );
+    assert_eq!(
+        validate_schema_source("schema"),
+        Err(vec![SchemaError::Typecheck(
+            SchemaTypeCheckError::UnknownFunction {
+                name: "schema".to_string(),
+                arg_types: vec![]
+            }
+        )])
+    );
+    assert!(matches!(
+        validate_schema_source("schema [")
+            .as_ref()
+            .map_err(Vec::as_slice),
+        Err([SchemaError::Parse(_)])
+    ));
+}
+
+#[test]
+fn requirement_check_against() {
+    assert_eq!(Requirement::exactly(5), Requirement::Exactly(5));
+    assert_eq!(Requirement::at_least(2), Requirement::AtLeast(2));
+    assert_eq!(Requirement::at_most(1), Requirement::AtMost(1));
+
+    assert_eq!(
+        Requirement::exactly(5).check_against(3),
+        Err(InfeasibleRequirement {
+            requirement: Requirement::Exactly(5),
+            available: 3,
+        })
+    );
+    assert_eq!(Requirement::exactly(3).check_against(3), Ok(()));
+    assert!(Requirement::at_least(4).check_against(3).is_err());
+    assert_eq!(Requirement::at_most(5).check_against(0), Ok(()));
+}
+
+#[test]
+fn categories_by_priority() {
+    let schema = compile_schema(
+        r#"schema "-" "_" [ category "Place" (at_least 0) ['home'], category "People" (at_least 2) ['nate', 'kim', 'sam'], category "Gear" (at_most 2) ['lens'], category "Media" (exactly 1) ['art'], category "Unused" (exactly 0) ['x'], category "Mood" (at_least 0) ['calm'] ]"#,
+    )
+    .unwrap();
+    let names: Vec<&str> = schema
+        .categories_by_priority()
+        .into_iter()
+        .map(|cat| cat.name.as_str())
+        .collect();
+    // every category that allows no tags is optional, whatever its upper limit
+    assert_eq!(
+        names,
+        vec!["Media", "People", "Place", "Gear", "Unused", "Mood"]
+    );
+}
+
+#[test]
+fn minimal_selection() {
+    let schema = compile_schema(
+        r#"schema "-" "_" [ category "Media" (exactly 1) ['art', 'photo'/'ph'], category "People" (at_least 2) ['nate', 'kim', 'sam'], category "Place" (at_least 0) ['home'], freeform "Tags" (exactly 1) ]"#,
+    )
+    .unwrap();
+    let state = schema.minimal_selection();
+    let selected: Vec<Vec<&str>> = state
+        .iter()
+        .map(|(_, kws)| {
+            kws.iter()
+                .filter(|(_, tf)| *tf)
+                .map(|(kw, _)| kw.id.as_str())
+                .collect()
+        })
+        .collect();
+    assert_eq!(
+        selected,
+        vec![vec!["art"], vec!["nate", "kim"], vec![], vec!["tag1"]]
+    );
+
+    let name = crate::filename::generate_with_salt(&schema, &state, "ABC123").unwrap();
+    assert_eq!(name, "ABC123-art-nate-kim-_-tag1");
+    assert_eq!(schema.parse(&name), Ok(state));
+}
+
+#[test]
+fn category_completions() {
+    let mut schema = compile_schema(TEST_SCHEMA).unwrap();
+    assert_eq!(schema.completions("Media", ""), vec!["art", "ph", "v"]);
+    assert_eq!(schema.completions("Media", "p"), vec!["ph"]);
+    assert_eq!(schema.completions("Media", "P"), Vec::<&str>::new());
+    assert_eq!(schema.completions("Media", "x"), Vec::<&str>::new());
+    assert_eq!(schema.completions("Places", ""), Vec::<&str>::new());
+
+    schema.case_insensitive = true;
+    assert_eq!(schema.completions("Media", "P"), vec!["ph"]);
+    schema.categories[0].1[0].id = "Art".to_string();
+    assert_eq!(schema.completions("Media", "a"), vec!["Art"]);
+    assert_eq!(schema.completions("Media", "AR"), vec!["Art"]);
+}
+
+#[test]
+fn selection_count() {
+    let cat = |requirement| Category {
+        name: "Media".to_string(),
+        requirement,
+        freeform: false,
+        intra_delim: None,
+        repeatable: false,
+    };
+    assert_eq!(cat(Requirement::Exactly(1)).selection_count(4), 4);
+    assert_eq!(cat(Requirement::AtMost(2)).selection_count(3), 7);
+    assert_eq!(cat(Requirement::AtLeast(0)).selection_count(2), 4);
+    assert_eq!(cat(Requirement::AtLeast(2)).selection_count(4), 11);
+    assert_eq!(cat(Requirement::Exactly(5)).selection_count(3), 0);
+    assert_eq!(cat(Requirement::AtLeast(0)).selection_count(200), u128::MAX);
+    assert_eq!(binomial(200, 10), 22_451_004_309_013_280);
+}
+
+/// a schema with keyword ids unique across every category, along with a selection that satisfies it
+#[cfg(test)]
+#[derive(Clone, Debug)]
+struct Generated(Schema, State);
+
+#[cfg(test)]
+impl quickcheck::Arbitrary for Generated {
+    fn arbitrary(g: &mut quickcheck::Gen) -> Self {
+        let mut schema = compile_schema(r#"schema "-" "_" []"#).unwrap();
+        let mut seen = std::collections::HashSet::new();
+        for i in 0..1 + usize::arbitrary(g) % 4 {
+            let kws: Vec<Keyword> = Vec::<Keyword>::arbitrary(g)
+                .into_iter()
+                .filter(|kw| seen.insert(kw.id.clone()))
+                .take(6)
+                .collect();
+            let cat = Category {
+                name: format!("Cat{i}"),
+                requirement: Requirement::AtLeast(0),
+                freeform: false,
+                intra_delim: None,
+                repeatable: false,
+            };
+            schema.categories.push((cat, kws));
+        }
+        let mut state = crate::app::to_empty_state(&schema);
+        for ((cat, _), (selected, kws)) in schema.categories.iter_mut().zip(state.iter_mut()) {
+            for (_, tf) in kws.iter_mut() {
+                *tf = bool::arbitrary(g);
+            }
+            if bool::arbitrary(g) {
+                let count = kws.iter().filter(|(_, tf)| *tf).count() as u8;
+                cat.requirement = Requirement::Exactly(count);
+                selected.requirement = cat.requirement;
+            }
+        }
+        Generated(schema, state)
+    }
+}
+
+#[test]
+fn parse_generated_schemas() {
+    fn prop(Generated(schema, state): Generated) -> bool {
+        let name = crate::filename::generate_with_salt(&schema, &state, "ABC123").unwrap();
+        schema.parse(&name) == Ok(state)
+    }
+    quickcheck::quickcheck(prop as fn(Generated) -> bool);
+}
+
+#[test]
+fn schema_to_expr_round_trip() {
+    fn prop(Generated(schema, _): Generated) -> bool {
+        typecheck::typecheck(schema_to_expr(&schema)) == Ok(schema)
+    }
+    quickcheck::quickcheck(prop as fn(Generated) -> bool);
+
+    let src = r#"schema "-" "_" [ repeatable (category "Media" (exactly 1) "+" ['art', 'photo'/'ph' : "photographs"]), freeform "Tags" (at_most 2) ]"#;
+    let schema = compile_schema(src).unwrap();
+    assert_eq!(schema_to_expr(&schema), parse::parse(src).unwrap());
+    assert_eq!(typecheck::typecheck(schema_to_expr(&schema)), Ok(schema));
+}
+
+#[test]
+fn source_diagnostics() {
+    let src = r#"schema "-" "_" [ category "A" ['x', ''], category "B" ['x'] ]"#;
+    assert_eq!(
+        diagnostics(src),
+        vec![
+            Diagnostic {
+                severity: Severity::Error,
+                code: "empty_keyword",
+                message: SchemaError::EmptyKeyword {
+                    category: "A".to_string(),
+                    id: String::new(),
+                }
+                .to_string(),
+                span: None,
+            },
+            Diagnostic {
+                severity: Severity::Error,
+                code: "overlapping_categories",
+                message: SchemaError::OverlappingCategories {
+                    subset: "B".to_string(),
+                    superset: "A".to_string(),
+                }
+                .to_string(),
+                span: None,
+            },
+        ]
+    );
+    let src = r#"schema "-" "_" [ category "A" ['x']"#;
+    assert_eq!(diagnostics(src)[0].span, Some(15..16));
+    // diagnostics agree with validation about what's an error
+    let src = r#"schema "-" "_" [ category "A" (at_most 0) ['x'] ]"#;
+    assert!(validate_schema_source(src).is_err());
+    assert_eq!(
+        diagnostics(src),
+        vec![Diagnostic {
+            severity: Severity::Error,
+            code: "empty_category",
+            message: SchemaError::EmptyCategory("A".to_string()).to_string(),
+            span: None,
+        }]
+    );
+    // lints are warnings and don't fail validation
+    let src = r#"schema "-" "_" [ category "A" ['x'], category "B" (at_least 0) ['xy'] ]"#;
+    assert_eq!(validate_schema_source(src), Ok(()));
+    assert_eq!(
+        diagnostics(src),
+        vec![Diagnostic {
+            severity: Severity::Warning,
+            code: "prefix_conflict",
+            message: SchemaError::PrefixConflict {
+                shorter: "x".to_string(),
+                longer: "xy".to_string(),
+            }
+            .to_string(),
+            span: None,
+        }]
+    );
+    assert_eq!(diagnostics(TEST_SCHEMA), vec![]);
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn source_diagnostics_json() {
+    let src = r#"schema "-" "_" [ category "A" ['x'], category "A" ['xy'] ]"#;
+    let json: serde_json::Value = serde_json::from_str(&diagnostics_json(src)).unwrap();
+    assert_eq!(
+        json,
+        serde_json::json!([
+            {
+                "severity": "error",
+                "code": "duplicate_category",
+                "message": "There is more than one category named \"A\".",
+                "span": null
+            },
+            {
+                "severity": "warning",
+                "code": "prefix_conflict",
+                "message": "Keyword id \"x\" is a prefix of keyword id \"xy\".",
+                "span": null
+            }
+        ])
+    );
+    let unclosed: serde_json::Value =
+        serde_json::from_str(&diagnostics_json(r#"schema "-" "_" [ category "A" ['x']"#)).unwrap();
+    assert_eq!(
+        unclosed[0]["span"],
+        serde_json::json!({ "start": 15, "end": 16 })
+    );
+}
+
+#[test]
+fn arbitrary_keywords() {
+    use quickcheck::Arbitrary;
+    let mut g = quickcheck::Gen::new(20);
+    let kws: Vec<Keyword> = (0..200).map(|_| Keyword::arbitrary(&mut g)).collect();
+    assert!(kws
+        .iter()
+        .all(|kw| !kw.id.is_empty() && !kw.id.contains(['-', '_'])));
+    // names and ids aren't always the same
+    assert!(kws.iter().any(|kw| kw.name != kw.id));
+
+    let kw = Keyword {
+        name: "photo".to_string(),
+        id: "pho".to_string(),
+        description: None,
+        draft: false,
+    };
+    let ids: Vec<String> = kw.shrink().map(|kw| kw.id).collect();
+    assert_eq!(ids, vec!["ph", "p"]);
+}
+
+#[test]
+fn validate_empty_category() {
+    for req in ["at_most 0", "exactly 0"] {
+        let schema = compile_schema(&format!(
+            r#"schema "-" "_" [ category "Media" (exactly 1) ['art'], category "Old" ({req}) ['x'] ]"#
+        ))
+        .unwrap();
+        assert_eq!(
+            schema.validate(),
+            Err(vec![SchemaError::EmptyCategory("Old".to_string())])
+        );
+        let allow = ValidateOptions {
+            allow_empty_category: true,
+            ..ValidateOptions::default()
+        };
+        assert_eq!(schema.validate_with(&allow), Ok(()));
+    }
+}
+
+#[test]
+fn validate_placeholder_salt() {
+    let mut schema = compile_schema(TEST_SCHEMA).unwrap();
+    assert_eq!(schema.validate(), Ok(()));
+    schema.empty = "ABCDEF".to_string();
+    assert_eq!(
+        schema.validate(),
+        Err(vec![SchemaError::PlaceholderIsSalt("ABCDEF".to_string())])
+    );
+}
+
+#[test]
+fn validate_category_names() {
+    let mut schema = compile_schema(
+        r#"schema "-" "_" [ category "Me-dia" (exactly 1) ['art'], category "A.B" ['x'] ]"#,
+    )
+    .unwrap();
+    assert_eq!(schema.validate(), Ok(()));
+    schema.labeled = true;
+    assert_eq!(
+        schema.validate(),
+        Err(vec![
+            SchemaError::IllegalCategoryName("Me-dia".to_string()),
+            SchemaError::IllegalCategoryName("A.B".to_string()),
+        ])
+    );
+}
+
+#[test]
+fn validate_overlapping_categories() {
+    let schema = compile_schema(
+        r#"schema "-" "_" [ category "A" ['x', 'y', 'z'], category "B" ['y', 'z'], category "C" ['z', 'w'] ]"#,
+    )
+    .unwrap();
+    assert_eq!(
+        schema.validate(),
+        Err(vec![SchemaError::OverlappingCategories {
+            subset: "B".to_string(),
+            superset: "A".to_string(),
+        }])
+    );
+    let allow = ValidateOptions {
+        allow_overlapping_categories: true,
+        ..ValidateOptions::default()
+    };
+    assert_eq!(schema.validate_with(&allow), Ok(()));
+}
+
+#[test]
+fn prefix_conflicts() {
+    let schema = compile_schema(
+        r#"schema "-" "_" [ category "Media" (exactly 1) ['ab', 'photo'/'ph'], category "People" (at_least 0) ['a', 'nate', 'phil'] ]"#,
+    )
+    .unwrap();
+    assert_eq!(
+        schema.prefix_conflicts(),
+        vec![
+            ("a".to_string(), "ab".to_string()),
+            ("ph".to_string(), "phil".to_string()),
+        ]
+    );
+    assert_eq!(
+        compile_schema(TEST_SCHEMA).unwrap().prefix_conflicts(),
+        vec![]
+    );
+}
+
+#[test]
+fn validate_id_lengths() {
+    let schema = compile_schema(TEST_SCHEMA).unwrap();
+    assert_eq!(schema.validate_id_lengths(4), Ok(()));
+    assert_eq!(
+        schema.validate_id_lengths(3),
+        Err(vec![("nate".to_string(), 4)])
+    );
+}
+
+#[test]
+fn builder() {
+    let keyword = |id: &str| Keyword {
+        name: id.to_string(),
+        id: id.to_string(),
+        description: None,
+        draft: false,
+    };
+    let category = |name: &str, requirement| Category {
+        name: name.to_string(),
+        requirement,
+        freeform: false,
+        intra_delim: None,
+        repeatable: false,
+    };
+    let schema = Schema::builder("-", "_")
+        .category(
+            category("Media", Requirement::Exactly(1)),
+            vec![keyword("art"), keyword("ph")],
+        )
+        .and_then(|b| {
+            b.category(
+                category("People", Requirement::AtLeast(0)),
+                vec![keyword("nate")],
+            )
+        })
+        .unwrap()
+        .build()
+        .unwrap();
+    assert_eq!(schema.categories.len(), 2);
+    assert_eq!(schema.parse("ABC123-ph-nate").map(|_| ()), Ok(()));
+
+    let builder = Schema::builder("-", "_");
+    assert_eq!(
+        builder
+            .clone()
+            .category(
+                category("Media", Requirement::Exactly(3)),
+                vec![keyword("art")]
+            )
+            .map(|_| ()),
+        Err(SchemaError::Infeasible {
+            category: "Media".to_string(),
+            requirement: Requirement::Exactly(3),
+            available: 1,
+        })
+    );
+    assert_eq!(
+        builder
+            .category(
+                category("Media", Requirement::Exactly(1)),
+                vec![keyword("art")]
+            )
+            .and_then(|b| b.category(
+                category("Media", Requirement::Exactly(1)),
+                vec![keyword("ph")]
+            ))
+            .map(|_| ()),
+        Err(SchemaError::DuplicateCategory("Media".to_string()))
+    );
+    // problems across categories are still caught when building
+    assert_eq!(
+        Schema::builder("-", "_")
+            .category(
+                category("Media", Requirement::Exactly(1)),
+                vec![keyword("a-b")]
+            )
+            .unwrap()
+            .build(),
+        Err(vec![SchemaError::DelimiterInKeyword {
+            category: "Media".to_string(),
+            id: "a-b".to_string(),
+        }])
+    );
+}
+
+#[test]
+fn category_count_bounds() {
+    let category = |requirement| Category {
+        name: "Media".to_string(),
+        requirement,
+        freeform: false,
+        intra_delim: None,
+        repeatable: false,
+    };
+    assert_eq!(
+        category(Requirement::Exactly(2)).count_bounds(),
+        (2, Some(2))
+    );
+    assert_eq!(category(Requirement::AtLeast(1)).count_bounds(), (1, None));
+    assert_eq!(
+        category(Requirement::AtMost(3)).count_bounds(),
+        (0, Some(3))
+    );
+}
+
+#[test]
+fn common_prefix() {
+    let old = compile_schema(
+        r#"schema "-" "_" [ category "Media" (exactly 1) ['art', 'photo'/'ph'], category "People" (at_least 0) ['nate'], category "Place" (at_most 1) ['home'], category "Year" ['2024'] ]"#,
+    )
+    .unwrap();
+    let new = compile_schema(
+        r#"schema "-" "_" [ category "Media" (exactly 1) ['art', 'photograph'/'ph' : "Photos"], category "People" (at_least 0) ['nate'], category "Place" (at_most 1) ['home', 'park'], category "Year" ['2024'] ]"#,
+    )
+    .unwrap();
+    let names: Vec<String> = old
+        .common_prefix(&new)
+        .into_iter()
+        .map(|cat| cat.name)
+        .collect();
+    assert_eq!(names, vec!["Media", "People"]);
+    assert_eq!(old.common_prefix(&old).len(), 4);
+    let reordered = compile_schema(
+        r#"schema "-" "_" [ category "People" (at_least 0) ['nate'], category "Media" (exactly 1) ['art', 'photo'/'ph'] ]"#,
+    )
+    .unwrap();
+    assert!(old.common_prefix(&reordered).is_empty());
+}
+
+#[test]
+fn fnv1a() {
+    let hash = |bytes: &[u8]| {
+        let mut hasher = Fnv1a::default();
+        hasher.write(bytes);
+        hasher.finish()
+    };
+    // reference values for 64-bit FNV-1a
+    assert_eq!(hash(b""), 0xcbf29ce484222325);
+    assert_eq!(hash(b"a"), 0xaf63dc4c8601ec8c);
+    assert_eq!(hash(b"foobar"), 0x85944171f73967e8);
+
+    // salt checksums hash the tags followed by the position of each character
+    let spec = SaltSpec::default();
+    let charset = spec.charset.as_bytes();
+    let expected: String = (0..spec.len)
+        .map(|i| {
+            let h = hash(format!("art-nate{}", i as u8 as char).as_bytes());
+            charset[(h % charset.len() as u64) as usize] as char
+        })
+        .collect();
+    assert_eq!(spec.checksum("art-nate"), expected);
+}
@@ -2,14 +2,14 @@ use super::{ExprU, ExprU::*, SchemaParseError};
 use nom::{
     branch::alt,
     bytes::complete::{tag, take_till},
-    character::complete::{alpha1, char, newline, space0, space1, u8},
-    combinator::{complete, eof, recognize, success},
+    character::complete::{alpha1, char, digit1, newline, one_of, space0, space1},
+    combinator::{complete, eof, opt, recognize, success, value},
     error::{ErrorKind, ParseError},
     multi::{many0, many0_count, many1},
-    sequence::{delimited, pair, preceded, separated_pair, terminated},
+    sequence::{delimited, pair, preceded, terminated},
     Err, IResult, InputLength, Parser,
 };
-use std::result::Result as StdResult;
+use std::{ops::Range, result::Result as StdResult};
 
 pub type Result<T> = StdResult<T, SchemaParseError>;
 
@@ -19,6 +19,7 @@ type NomParseResult<'a, O> = StdResult<(&'a str, O), nom::Err<NomParseError<&'a
 pub enum NomParseError<I> {
     Custom(SchemaParseError),
     Nom(I, ErrorKind),
+    Unclosed { open: char, at: I },
 }
 
 impl<I> ParseError<I> for NomParseError<I> {
@@ -56,11 +57,267 @@ impl<I> From<nom::Err<(I, ErrorKind)>> for NomParseError<I> {
     }
 }
 
+/// options that change which schema source text is accepted.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ParseConfig {
+    /// reject multi-digit numbers like `007` that start with a zero
+    pub reject_leading_zeros: bool,
+    pub comment: CommentStyle,
+}
+
+/// which characters start a line comment
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CommentStyle {
+    #[default]
+    Hash,
+    Semicolon,
+    DoubleSlash,
+}
+
+impl CommentStyle {
+    pub fn introducer(&self) -> &'static str {
+        match self {
+            Self::Hash => "#",
+            Self::Semicolon => ";",
+            Self::DoubleSlash => "//",
+        }
+    }
+}
+
 pub fn parse(input: &str) -> Result<ExprU> {
-    match complete(expr).parse(input) {
+    parse_with(input, ParseConfig::default())
+}
+
+/// number of arguments to the top level schema constructor
+const SCHEMA_ARITY: usize = 3;
+
+/// source text after a complete top level schema, which would otherwise parse as more arguments.
+fn schema_leftover(cfg: ParseConfig, input: &str) -> Option<&str> {
+    let arg = |i| preceded(|i| line_space1(cfg, i), |i| expr(cfg, i)).parse(i);
+    let (input, _) = line_space0(cfg, input).ok()?;
+    let (input, _) = lexeme_vert_allowed(identifier).parse(input).ok()?;
+    let (mut input, _) = expr(cfg, input).ok()?;
+    for _ in 1..SCHEMA_ARITY {
+        (input, _) = arg(input).ok()?;
+    }
+    let (input, _) = line_space0(cfg, input).ok()?;
+    Some(input)
+}
+
+/// byte ranges of each element of the top level schema's category list.
+fn category_spans(cfg: ParseConfig, input: &str) -> Option<Vec<Range<usize>>> {
+    let offset = |rest: &str| input.len() - rest.len();
+    let arg = |i| preceded(|i| line_space1(cfg, i), |i| expr(cfg, i)).parse(i);
+    let (rest, _) = line_space0(cfg, input).ok()?;
+    let (rest, _) = lexeme_vert_allowed(identifier).parse(rest).ok()?;
+    let (rest, _) = expr(cfg, rest).ok()?;
+    let (rest, _) = arg(rest).ok()?;
+    let (mut rest, _) = preceded(|i| line_space1(cfg, i), char('['))
+        .parse(rest)
+        .ok()?;
+    let mut spans = vec![];
+    loop {
+        (rest, _) = line_space0(cfg, rest).ok()?;
+        if spans.is_empty() && rest.starts_with(']') {
+            return Some(spans);
+        }
+        let start = offset(rest);
+        (rest, _) = expr(cfg, rest).ok()?;
+        spans.push(start..offset(rest));
+        (rest, _) = line_space0(cfg, rest).ok()?;
+        match rest.chars().next()? {
+            ',' => rest = &rest[1..],
+            ']' => return Some(spans),
+            _ => return None,
+        }
+    }
+}
+
+/// a parsed schema along with the byte range of each of its categories in the source,
+/// so an edit can be reparsed one category at a time.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SpannedExpr {
+    pub expr: ExprU,
+    /// empty when the categories couldn't be found, which makes the next reparse a full one
+    pub category_spans: Vec<Range<usize>>,
+}
+
+pub fn parse_spanned(input: &str) -> Result<SpannedExpr> {
+    parse_spanned_with(input, ParseConfig::default())
+}
+
+pub fn parse_spanned_with(input: &str, cfg: ParseConfig) -> Result<SpannedExpr> {
+    let expr = parse_with(input, cfg)?;
+    Ok(SpannedExpr {
+        expr,
+        category_spans: category_spans(cfg, input).unwrap_or_default(),
+    })
+}
+
+/// parses source text after an edit by reparsing only the category the edit was in and
+/// swapping it into the expression parsed from the old source. `edit` is the byte range
+/// of `old_src` that was replaced. Edits that aren't within one category parse all of `new_src`.
+pub fn reparse(
+    old: &SpannedExpr,
+    old_src: &str,
+    new_src: &str,
+    edit: Range<usize>,
+) -> Result<SpannedExpr> {
+    reparse_with(old, old_src, new_src, edit, ParseConfig::default())
+}
+
+pub fn reparse_with(
+    old: &SpannedExpr,
+    old_src: &str,
+    new_src: &str,
+    edit: Range<usize>,
+    cfg: ParseConfig,
+) -> Result<SpannedExpr> {
+    let spliced = || -> Option<SpannedExpr> {
+        let FnU { name, args } = &old.expr else {
+            return None;
+        };
+        let Some(ListU(categories)) = args.get(2) else {
+            return None;
+        };
+        let spans = &old.category_spans;
+        if spans.len() != categories.len() {
+            return None;
+        }
+        let i = spans
+            .iter()
+            .position(|span| span.start <= edit.start && edit.end <= span.end)?;
+        // where the edit and anything after it end up in the new source
+        let shift = |end: usize| (end + new_src.len()).checked_sub(old_src.len());
+        let inserted = new_src.get(edit.start..shift(edit.end)?)?;
+        // a new comment could run past the end of the category
+        if inserted.contains(cfg.comment.introducer()) {
+            return None;
+        }
+        let text = new_src.get(spans[i].start..shift(spans[i].end)?)?;
+        let ("", category) = complete(|i| expr(cfg, i)).parse(text).ok()? else {
+            return None;
+        };
+        let mut categories = categories.clone();
+        categories[i] = category;
+        let mut args = args.clone();
+        args[2] = ListU(categories);
+        let category_spans = spans
+            .iter()
+            .enumerate()
+            .map(|(j, span)| match j {
+                j if j < i => Some(span.clone()),
+                j if j == i => Some(span.start..shift(span.end)?),
+                _ => Some(shift(span.start)?..shift(span.end)?),
+            })
+            .collect::<Option<Vec<Range<usize>>>>()?;
+        Some(SpannedExpr {
+            expr: FnU {
+                name: name.clone(),
+                args,
+            },
+            category_spans,
+        })
+    };
+    match spliced() {
+        Some(spanned) => Ok(spanned),
+        None => parse_spanned_with(new_src, cfg),
+    }
+}
+
+/// whether two expressions have the same structure and values, regardless of the layout
+/// and comments of the source they came from. Tests of `unparse` and `reparse` use this
+/// rather than `==` so metadata about the source can be added to `ExprU` later.
+pub fn exprs_equivalent(a: &ExprU, b: &ExprU) -> bool {
+    match (a, b) {
+        (
+            KeywordU {
+                name,
+                id,
+                description,
+            },
+            KeywordU {
+                name: name2,
+                id: id2,
+                description: description2,
+            },
+        ) => name == name2 && id == id2 && description == description2,
+        (StringU(a), StringU(b)) => a == b,
+        (NatU(a), NatU(b)) => a == b,
+        (ListU(xs), ListU(ys)) => {
+            xs.len() == ys.len() && xs.iter().zip(ys).all(|(x, y)| exprs_equivalent(x, y))
+        }
+        (
+            FnU { name, args },
+            FnU {
+                name: name2,
+                args: args2,
+            },
+        ) => {
+            name == name2
+                && args.len() == args2.len()
+                && args.iter().zip(args2).all(|(x, y)| exprs_equivalent(x, y))
+        }
+        _ => false,
+    }
+}
+
+/// prints an expression as source text that parses back to the same expression.
+pub fn unparse(expr: &ExprU) -> String {
+    match expr {
+        KeywordU {
+            name,
+            id,
+            description,
+        } => {
+            let mut s = escape('\'', name);
+            if id != name {
+                s.push('/');
+                s.push_str(&escape('\'', id));
+            }
+            if let Some(description) = description {
+                s.push_str(" : ");
+                s.push_str(&escape('"', description));
+            }
+            s
+        }
+        StringU(s) => escape('"', s),
+        NatU(n) => n.to_string(),
+        ListU(xs) => format!(
+            "[{}]",
+            xs.iter().map(unparse).collect::<Vec<String>>().join(", ")
+        ),
+        FnU { name, args } => {
+            let mut s = name.clone();
+            for arg in args {
+                s.push(' ');
+                match arg {
+                    FnU { args, .. } if !args.is_empty() => {
+                        s.push('(');
+                        s.push_str(&unparse(arg));
+                        s.push(')');
+                    }
+                    arg => s.push_str(&unparse(arg)),
+                }
+            }
+            s
+        }
+    }
+}
+
+pub fn parse_with(input: &str, cfg: ParseConfig) -> Result<ExprU> {
+    // editors usually save files with trailing newlines
+    let space = |i| line_space0(cfg, i);
+    match complete(delimited(space, |i| expr(cfg, i), space)).parse(input) {
         Ok((leftover, _)) if !leftover.is_empty() => {
             Err(SchemaParseError::UnexpectedInput(leftover.to_string()))
         }
+        Ok((_, FnU { name, args })) if name == "schema" && args.len() > SCHEMA_ARITY => {
+            let leftover = schema_leftover(cfg, input).unwrap_or_default();
+            Err(SchemaParseError::TrailingExpression(
+                leftover.trim_end().to_string(),
+            ))
+        }
         Ok((_, schema @ FnU { .. })) => Ok(schema),
         Ok((_, _)) => Err(SchemaParseError::MustStartWithSchemaConstructor),
         Err(e) => match e {
@@ -69,6 +326,10 @@ pub fn parse(input: &str) -> Result<ExprU> {
                 NomParseError::Nom(input, _kind) => {
                     Err(SchemaParseError::UnexpectedInput(input.to_string()))
                 }
+                NomParseError::Unclosed { open, at } => Err(SchemaParseError::UnclosedDelimiter {
+                    open,
+                    at: input.len() - at.len(),
+                }),
             },
             // call to complete skips this branch
             nom::Err::Incomplete(_) => {
@@ -78,23 +339,23 @@ pub fn parse(input: &str) -> Result<ExprU> {
     }
 }
 
-fn expr(input: &str) -> NomParseResult<ExprU> {
+fn expr(cfg: ParseConfig, input: &str) -> NomParseResult<'_, ExprU> {
     alt((
-        parens(expr),
-        list,
-        func,
-        nat.map(NatU),
+        parens(|i| expr(cfg, i)),
+        |i| list(cfg, i),
+        |i| func(cfg, i),
+        (|i| nat(cfg, i)).map(NatU),
         // keywords are above commands because the syntax leads with a string
-        keyword,
+        |i| documented_keyword(cfg, i),
         string.map(StringU),
     ))(input)
 }
 
-fn func(input: &str) -> NomParseResult<ExprU> {
+fn func(cfg: ParseConfig, input: &str) -> NomParseResult<'_, ExprU> {
     let (input, name) = lexeme_vert_allowed(identifier).parse(input)?;
-    let (input, args) = sep_by0(line_space1, expr).parse(input)?;
+    let (input, args) = sep_by0(|i| line_space1(cfg, i), |i| expr(cfg, i)).parse(input)?;
     // eat any trailing whitespace
-    let (input, _) = line_space0.parse(input)?;
+    let (input, _) = line_space0(cfg, input)?;
     Ok((
         input,
         FnU {
@@ -104,84 +365,191 @@ fn func(input: &str) -> NomParseResult<ExprU> {
     ))
 }
 
-fn list(input: &str) -> NomParseResult<ExprU> {
+fn list(cfg: ParseConfig, input: &str) -> NomParseResult<'_, ExprU> {
+    let space = |i| line_space0(cfg, i);
     let (input, args) = between(
         '[',
         ']',
         alt((
             sep_by1(
-                delimited(line_space0, tag(","), line_space0),
-                delimited(line_space0, expr, line_space0),
+                delimited(space, tag(","), space),
+                delimited(space, |i| expr(cfg, i), space),
             ),
-            line_space0.map(|_| vec![]),
+            space.map(|_| vec![]),
         )),
     )
     .parse(input)?;
     Ok((input, ListU(args)))
 }
 
-fn identifier(input: &str) -> NomParseResult<&str> {
+fn identifier(input: &str) -> NomParseResult<'_, &str> {
     recognize(pair(alpha1, many0_count(alt((alpha1, tag("_"))))))(input)
 }
 
-fn keyword_half(input: &str) -> NomParseResult<String> {
-    between('\'', '\'', take_till(|x| x == '\''))
-        .map(|x| x.to_string())
-        .parse(input)
+fn keyword_half(input: &str) -> NomParseResult<'_, String> {
+    between('\'', '\'', |i| escaped_text('\'', i)).parse(input)
 }
 
-fn keyword(input: &str) -> NomParseResult<ExprU> {
-    alt((
-        separated_pair(keyword_half, tag("/"), keyword_half).map(|(name, id)| KeywordU {
-            name: name.clone(),
-            id: id.clone(),
-        }),
-        keyword_half.map(|name| KeywordU {
-            name: name.clone(),
-            id: name.clone(),
-        }),
-    ))(input)
+fn keyword(input: &str) -> NomParseResult<'_, ExprU> {
+    let (input, name) = keyword_half(input)?;
+    // only a quoted id after the slash makes it part of the keyword
+    let (input, id) = opt(preceded(tag("/"), keyword_half)).parse(input)?;
+    let id = id.unwrap_or_else(|| name.clone());
+    let (input, description) =
+        opt(preceded(delimited(space0, char(':'), space0), string)).parse(input)?;
+    Ok((
+        input,
+        KeywordU {
+            name,
+            id,
+            description,
+        },
+    ))
 }
 
-fn string(input: &str) -> NomParseResult<String> {
-    between('"', '"', take_till(|x| x == '"'))
-        .map(|x| x.to_string())
-        .parse(input)
+/// a keyword without an explicit description takes the comment on the rest of its line instead.
+fn documented_keyword(cfg: ParseConfig, input: &str) -> NomParseResult<'_, ExprU> {
+    let (input, kw) = keyword(input)?;
+    let KeywordU {
+        name,
+        id,
+        description: None,
+    } = kw
+    else {
+        return Ok((input, kw));
+    };
+    let (input, text) = opt(preceded(
+        pair(space0, tag(cfg.comment.introducer())),
+        take_till(|x| x == '\n'),
+    ))
+    .parse(input)?;
+    let description = text.map(str::trim).filter(|x| !x.is_empty());
+    Ok((
+        input,
+        KeywordU {
+            name,
+            id,
+            description: description.map(str::to_string),
+        },
+    ))
 }
 
-fn nat(input: &str) -> NomParseResult<u8> {
-    u8(input)
+fn string(input: &str) -> NomParseResult<'_, String> {
+    between('"', '"', |i| escaped_text('"', i)).parse(input)
 }
 
-fn indent(input: &str) -> NomParseResult<&str> {
+/// text up to the next unescaped quote, decoding `\\`, `\n`, `\t`, and an escaped quote.
+/// keep in sync with `escape`.
+fn escaped_text(quote: char, input: &str) -> NomParseResult<'_, String> {
+    let mut text = String::new();
+    let mut chars = input.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            c if c == quote => return Ok((&input[i..], text)),
+            '\\' => match chars.next() {
+                Some((_, 'n')) => text.push('\n'),
+                Some((_, 't')) => text.push('\t'),
+                Some((_, '\\')) => text.push('\\'),
+                Some((_, c)) if c == quote => text.push(c),
+                Some((_, c)) => {
+                    return Err(Err::Failure(
+                        SchemaParseError::InvalidEscape(format!("\\{c}")).into(),
+                    ))
+                }
+                // leave the missing close quote for the caller to report
+                None => return Ok((&input[input.len()..], text)),
+            },
+            c => text.push(c),
+        }
+    }
+    Ok((&input[input.len()..], text))
+}
+
+/// quotes text so that it parses back to the same value.
+fn escape(quote: char, text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len() + 2);
+    escaped.push(quote);
+    for c in text.chars() {
+        match c {
+            '\n' => escaped.push_str("\\n"),
+            '\t' => escaped.push_str("\\t"),
+            '\\' => escaped.push_str("\\\\"),
+            c if c == quote => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            c => escaped.push(c),
+        }
+    }
+    escaped.push(quote);
+    escaped
+}
+
+fn nat(cfg: ParseConfig, input: &str) -> NomParseResult<'_, u8> {
+    let (rest, digits) = digit1(input)?;
+    if cfg.reject_leading_zeros && digits.len() > 1 && digits.starts_with('0') {
+        return Err(Err::Failure(
+            SchemaParseError::LeadingZero(digits.to_string()).into(),
+        ));
+    }
+    // digit1 only matches ascii digits, so overflow is the only way this fails
+    match digits.parse::<u8>() {
+        Ok(n) => Ok((rest, n)),
+        Err(_) => Err(Err::Failure(
+            SchemaParseError::NatOverflow {
+                value: digits.to_string(),
+                max: u8::MAX.into(),
+            }
+            .into(),
+        )),
+    }
+}
+
+fn indent(input: &str) -> NomParseResult<'_, &str> {
     alt((tag("  "), tag("\t")))(input).map(|(rest, _)| (rest, ""))
 }
 
-fn line_space1(input: &str) -> NomParseResult<&str> {
-    many1(alt((char(' '), char('\t'), char('\n'))))(input).map(|(rest, _)| (rest, ""))
+/// whitespace including newlines and comments
+fn line_space1(cfg: ParseConfig, input: &str) -> NomParseResult<'_, &str> {
+    many1(alt((value((), one_of(" \t\n")), |i| comment(cfg, i))))(input).map(|(rest, _)| (rest, ""))
+}
+
+/// whitespace including newlines and comments
+fn line_space0(cfg: ParseConfig, input: &str) -> NomParseResult<'_, &str> {
+    many0(alt((value((), one_of(" \t\n")), |i| comment(cfg, i))))(input).map(|(rest, _)| (rest, ""))
 }
 
-fn line_space0(input: &str) -> NomParseResult<&str> {
-    many0(alt((char(' '), char('\t'), char('\n'))))(input).map(|(rest, _)| (rest, ""))
+/// comments run from the introducer to the end of the line
+fn comment(cfg: ParseConfig, input: &str) -> NomParseResult<'_, ()> {
+    value(
+        (),
+        pair(tag(cfg.comment.introducer()), take_till(|x| x == '\n')),
+    )(input)
 }
 
-fn parens<'a, F, O, E: ParseError<&'a str>>(inner: F) -> impl Parser<&'a str, O, E>
+fn parens<'a, F, O>(inner: F) -> impl Parser<&'a str, O, NomParseError<&'a str>>
 where
-    F: Parser<&'a str, O, E>,
+    F: Parser<&'a str, O, NomParseError<&'a str>>,
 {
     between('(', ')', inner)
 }
 
 /// takes two characters and returns a parser for the str between them.
-fn between<'a, O, E: ParseError<&'a str>, F>(
-    l: char,
-    r: char,
-    inner: F,
-) -> impl Parser<&'a str, O, E>
+/// fails without backtracking if the input ends before the closing character.
+fn between<'a, O, F>(l: char, r: char, mut inner: F) -> impl FnMut(&'a str) -> NomParseResult<'a, O>
 where
-    F: Parser<&'a str, O, E>,
+    F: Parser<&'a str, O, NomParseError<&'a str>>,
 {
-    terminated(preceded(char(l), inner), char(r))
+    move |input: &'a str| {
+        let (rest, x) = preceded(char(l), |i| inner.parse(i)).parse(input)?;
+        match char(r).parse(rest) {
+            Ok((rest, _)) => Ok((rest, x)),
+            Err(Err::Error(_)) if rest.trim().is_empty() => {
+                Err(Err::Failure(NomParseError::Unclosed { open: l, at: input }))
+            }
+            Err(e) => Err(e),
+        }
+    }
 }
 
 fn sep_by0<I, O1, O2: Clone, E: ParseError<I>, F, G>(sep: F, value: G) -> impl Parser<I, Vec<O2>, E>
@@ -270,14 +638,17 @@ fn top_level() {
                             KeywordU {
                                 name: "art".to_string(),
                                 id: "art".to_string(),
+                                description: None,
                             },
                             KeywordU {
                                 name: "photo".to_string(),
                                 id: "ph".to_string(),
+                                description: None,
                             },
                             KeywordU {
                                 name: "video".to_string(),
                                 id: "v".to_string(),
+                                description: None,
                             },
                         ]),
                     ],
@@ -293,6 +664,7 @@ fn top_level() {
                         ListU(vec![KeywordU {
                             name: "nate".to_string(),
                             id: "nate".to_string(),
+                            description: None,
                         }]),
                     ],
                 },
@@ -303,15 +675,103 @@ fn top_level() {
     assert_eq!(Ok(expr), parse(input));
 }
 
+#[test]
+fn top_level_trailing_newlines() {
+    let input = r#"schema "-" "_" [ category "Media" (exactly 1) ['art', 'photo'/'ph', 'video'/'v'], category "People" (at_least 0) ['nate']]"#;
+    let expected = parse(input);
+    assert!(expected.is_ok());
+    assert_eq!(parse(&format!("{input}\n\n")), expected);
+    assert_eq!(parse(&format!("{input}\n  \n\t")), expected);
+    assert_eq!(parse(&format!("({input})\n\n")), expected);
+}
+
+#[test]
+fn parse_unclosed() {
+    assert_eq!(
+        parse(r#"schema "-" "_" [ category "Media" (exactly 1) ['art']"#),
+        Err(SchemaParseError::UnclosedDelimiter { open: '[', at: 15 })
+    );
+    assert_eq!(
+        parse(r#"schema "-" "_" [ category "Media" (exactly 1"#),
+        Err(SchemaParseError::UnclosedDelimiter { open: '(', at: 34 })
+    );
+    assert_eq!(
+        parse("schema \"-\" \"_\" [\n  "),
+        Err(SchemaParseError::UnclosedDelimiter { open: '[', at: 15 })
+    );
+}
+
+#[test]
+fn parse_comments() {
+    // comments after a keyword describe it
+    let expected = parse(
+        r#"schema "-" "_" [ category "Media" (exactly 1) ['art', 'photo'/'ph' : "photographs"], category "People" (at_least 0) ['nate']]"#,
+    );
+    assert!(expected.is_ok());
+
+    let hash = r#"# media library
+schema "-" "_" # delim and empty
+  [ category "Media" (exactly 1) # one medium per file
+      [ 'art'
+      , 'photo'/'ph' # photographs
+      ]
+  , category "People" (at_least 0) ['nate']
+  ]
+"#;
+    let slash = hash.replace('#', "//");
+    let double_slash = ParseConfig {
+        comment: CommentStyle::DoubleSlash,
+        ..ParseConfig::default()
+    };
+
+    assert_eq!(parse(hash), expected);
+    assert_eq!(parse_with(&slash, double_slash), expected);
+    assert!(parse(&slash).is_err());
+    assert!(parse_with(hash, double_slash).is_err());
+}
+
+#[test]
+fn parse_trailing_comments() {
+    let schema = r#"schema "-" "_" [ category "People" (at_least 0) ['nate']]"#;
+    let expected = parse(schema);
+    assert!(expected.is_ok());
+
+    assert_eq!(parse(&format!("{schema}\n# footer note\n")), expected);
+    assert_eq!(parse(&format!("{schema} # same line\n\n")), expected);
+    // a final comment doesn't need a newline
+    assert_eq!(
+        parse(&format!("{schema}\n# footer\n# no newline")),
+        expected
+    );
+    assert!(parse(&format!("{schema}\n# footer\n]")).is_err());
+}
+
+#[test]
+fn parse_trailing_expression() {
+    let schema = r#"schema "-" "_" [ category "People" (at_least 0) ['nate']]"#;
+    assert_eq!(
+        parse(&format!("{schema} []")),
+        Err(SchemaParseError::TrailingExpression("[]".to_string()))
+    );
+    assert_eq!(
+        parse(&format!("{schema}\n# stray\n[] \"x\"\n")),
+        Err(SchemaParseError::TrailingExpression("[] \"x\"".to_string()))
+    );
+    // other functions still take any number of arguments
+    assert!(parse("foo 1 2 3 4").is_ok());
+}
+
 #[test]
 fn parse_parens() {
-    assert_eq!(expr("(0)"), Ok(("", NatU(0))));
-    assert_eq!(expr("((0))"), Ok(("", NatU(0))));
-    assert_eq!(expr("([(0)])"), Ok(("", ListU(vec![NatU(0)]))));
+    let cfg = ParseConfig::default();
+    assert_eq!(expr(cfg, "(0)"), Ok(("", NatU(0))));
+    assert_eq!(expr(cfg, "((0))"), Ok(("", NatU(0))));
+    assert_eq!(expr(cfg, "([(0)])"), Ok(("", ListU(vec![NatU(0)]))));
 }
 
 #[test]
 fn parse_func() {
+    let cfg = ParseConfig::default();
     let foo0 = FnU {
         name: "foo".to_string(),
         args: vec![NatU(0)],
@@ -337,21 +797,34 @@ fn parse_func() {
         args: vec![NatU(99), NatU(99), ListU(vec![ListU(vec![])])],
     };
 
-    assert_eq!(func("foo 0"), Ok(("", foo0.clone())));
-    assert_eq!(func("foo []"), Ok(("", fool.clone())));
-    assert_eq!(func("foo\n  0"), Ok(("", foo0.clone())));
-    assert_eq!(func("foo\n\t0"), Ok(("", foo0.clone())));
-    assert_eq!(func("foo \n  0"), Ok(("", foo0.clone())));
-    assert_eq!(func("foo \n  [ 0\n  , 1\n  ]"), Ok(("", fool01.clone())));
+    assert_eq!(func(cfg, "foo 0"), Ok(("", foo0.clone())));
+    assert_eq!(func(cfg, "foo []"), Ok(("", fool.clone())));
+    assert_eq!(func(cfg, "foo\n  0"), Ok(("", foo0.clone())));
+    assert_eq!(func(cfg, "foo\n\t0"), Ok(("", foo0.clone())));
+    assert_eq!(func(cfg, "foo \n  0"), Ok(("", foo0.clone())));
+    assert_eq!(
+        func(cfg, "foo \n  [ 0\n  , 1\n  ]"),
+        Ok(("", fool01.clone()))
+    );
     assert_eq!(
-        func("foo 99\n  [ 0\n  , 1\n  ]"),
+        func(cfg, "foo 99\n  [ 0\n  , 1\n  ]"),
         Ok(("", foo99l01.clone()))
     );
-    assert_eq!(func("foo\n  [  0\n  , 1\n  ]"), Ok(("", fool01.clone())));
-    assert_eq!(func("foo 99 99\n  [  []  ]"), Ok(("", foo9999ll.clone())));
-    assert!(func(r#"category "Media" (exactly 1) ["art", "photo"/"ph", "video"/"v"]"#).is_ok());
     assert_eq!(
-        func("boop []]"),
+        func(cfg, "foo\n  [  0\n  , 1\n  ]"),
+        Ok(("", fool01.clone()))
+    );
+    assert_eq!(
+        func(cfg, "foo 99 99\n  [  []  ]"),
+        Ok(("", foo9999ll.clone()))
+    );
+    assert!(func(
+        cfg,
+        r#"category "Media" (exactly 1) ["art", "photo"/"ph", "video"/"v"]"#
+    )
+    .is_ok());
+    assert_eq!(
+        func(cfg, "boop []]"),
         Ok((
             "]",
             FnU {
@@ -362,20 +835,46 @@ fn parse_func() {
     );
 }
 
+#[test]
+fn parse_list_garbage() {
+    let cfg = ParseConfig::default();
+    // nothing that fails to parse as an element falls through to the empty list
+    for input in ["[ % ]", "[ %", "[\n\t% ]", "[ 0 % ]", "[ 0, ]", "[ , ]"] {
+        assert!(list(cfg, input).is_err(), "{input:?} parsed as a list");
+    }
+    assert!(parse("schema \"-\" \"_\" [ % ]").is_err());
+    // bare identifiers are function calls, so this parses but doesn't typecheck
+    assert_eq!(
+        list(cfg, "[ garbage ]"),
+        Ok((
+            "",
+            ListU(vec![FnU {
+                name: "garbage".to_string(),
+                args: vec![]
+            }])
+        ))
+    );
+    assert!(super::compile_schema("schema \"-\" \"_\" [ garbage ]").is_err());
+}
+
 #[test]
 fn parse_list() {
-    assert_eq!(list("[]"), Ok(("", ListU(vec![]))));
-    assert_eq!(list("[[]]"), Ok(("", ListU(vec![ListU(vec![])]))));
-    assert_eq!(list("[0,1]"), Ok(("", ListU(vec![NatU(0), NatU(1)]))));
-    assert_eq!(list("[0, 1]"), Ok(("", ListU(vec![NatU(0), NatU(1)]))));
-    assert_eq!(list("[ 0 ]"), Ok(("", ListU(vec![NatU(0)]))));
-    assert_eq!(list("[ ]"), Ok(("", ListU(vec![]))));
-    assert_eq!(list("[\n\t]"), Ok(("", ListU(vec![]))));
-    assert_eq!(list("[ 0\n\t]"), Ok(("", ListU(vec![NatU(0)]))));
-    assert_eq!(list("[ 0\n, 1\n]"), Ok(("", ListU(vec![NatU(0), NatU(1)]))));
-    assert_eq!(list("[]]"), Ok(("]", ListU(vec![]))));
-    assert_eq!(
-        list("[boop []]"),
+    let cfg = ParseConfig::default();
+    assert_eq!(list(cfg, "[]"), Ok(("", ListU(vec![]))));
+    assert_eq!(list(cfg, "[[]]"), Ok(("", ListU(vec![ListU(vec![])]))));
+    assert_eq!(list(cfg, "[0,1]"), Ok(("", ListU(vec![NatU(0), NatU(1)]))));
+    assert_eq!(list(cfg, "[0, 1]"), Ok(("", ListU(vec![NatU(0), NatU(1)]))));
+    assert_eq!(list(cfg, "[ 0 ]"), Ok(("", ListU(vec![NatU(0)]))));
+    assert_eq!(list(cfg, "[ ]"), Ok(("", ListU(vec![]))));
+    assert_eq!(list(cfg, "[\n\t]"), Ok(("", ListU(vec![]))));
+    assert_eq!(list(cfg, "[ 0\n\t]"), Ok(("", ListU(vec![NatU(0)]))));
+    assert_eq!(
+        list(cfg, "[ 0\n, 1\n]"),
+        Ok(("", ListU(vec![NatU(0), NatU(1)])))
+    );
+    assert_eq!(list(cfg, "[]]"), Ok(("]", ListU(vec![]))));
+    assert_eq!(
+        list(cfg, "[boop []]"),
         Ok((
             "",
             ListU(vec![FnU {
@@ -384,6 +883,22 @@ fn parse_list() {
             }])
         ))
     );
+    assert_eq!(
+        list(cfg, r#"["6\" vinyl", "a\\b\tc\n"]"#),
+        Ok((
+            "",
+            ListU(vec![
+                StringU("6\" vinyl".to_string()),
+                StringU("a\\b\tc\n".to_string())
+            ])
+        ))
+    );
+    let schema = super::compile_schema(
+        r#"schema "-" "_" [ category "6\" vinyl" (exactly 1) ['side \'A\'', 'b'] ]"#,
+    )
+    .unwrap();
+    assert_eq!(schema.categories[0].0.name, "6\" vinyl");
+    assert_eq!(schema.categories[0].1[0].id, "side 'A'");
 }
 
 #[test]
@@ -394,7 +909,8 @@ fn parse_keyword() {
             "",
             KeywordU {
                 name: "abc".to_string(),
-                id: "a".to_string()
+                id: "a".to_string(),
+                description: None,
             }
         ))
     );
@@ -404,22 +920,225 @@ fn parse_keyword() {
             "",
             KeywordU {
                 name: "abc".to_string(),
-                id: "abc".to_string()
+                id: "abc".to_string(),
+                description: None,
             }
         ))
     );
+    assert_eq!(
+        keyword(r#"'12\' vinyl'/'12in' : "the \"long\" ones""#),
+        Ok((
+            "",
+            KeywordU {
+                name: "12' vinyl".to_string(),
+                id: "12in".to_string(),
+                description: Some("the \"long\" ones".to_string()),
+            }
+        ))
+    );
+
+    // the decoded id is what ends up in filenames
+    let schema = super::compile_schema(
+        r#"schema "-" "_" [ category "Format" (exactly 1) ['12\'', '7in'] ]"#,
+    )
+    .unwrap();
+    let state = schema.parse("ABC123-12'").unwrap();
+    assert_eq!(
+        crate::filename::generate_with_salt(&schema, &state, "ABC123"),
+        Ok("ABC123-12'".to_string())
+    );
+}
+
+#[test]
+fn parse_keyword_slash() {
+    let a = KeywordU {
+        name: "a".to_string(),
+        id: "a".to_string(),
+        description: None,
+    };
+    assert_eq!(keyword("'a'"), Ok(("", a.clone())));
+    assert_eq!(
+        keyword("'a'/'b'"),
+        Ok((
+            "",
+            KeywordU {
+                name: "a".to_string(),
+                id: "b".to_string(),
+                description: None,
+            }
+        ))
+    );
+    // a dangling slash is left for the caller
+    assert_eq!(keyword("'a'/"), Ok(("/", a.clone())));
+    assert_eq!(keyword("'a'/b"), Ok(("/b", a)));
+    assert_eq!(
+        parse("'a'/"),
+        Err(SchemaParseError::UnexpectedInput("/".to_string()))
+    );
+
+    // double quotes are always plain strings
+    let cfg = ParseConfig::default();
+    assert_eq!(expr(cfg, r#""a""#), Ok(("", StringU("a".to_string()))));
+    assert_eq!(
+        expr(cfg, r#""a"/"b""#),
+        Ok((r#"/"b""#, StringU("a".to_string())))
+    );
+}
+
+#[test]
+fn parse_keyword_description() {
+    let photo = KeywordU {
+        name: "photo".to_string(),
+        id: "ph".to_string(),
+        description: Some("Photographs".to_string()),
+    };
+    assert_eq!(
+        keyword(r#"'photo'/'ph' : "Photographs""#),
+        Ok(("", photo.clone()))
+    );
+    assert_eq!(
+        keyword(r#"'photo'/'ph':"Photographs""#),
+        Ok(("", photo.clone()))
+    );
+    assert_eq!(
+        keyword(r#"'art' : "Paintings and drawings""#),
+        Ok((
+            "",
+            KeywordU {
+                name: "art".to_string(),
+                id: "art".to_string(),
+                description: Some("Paintings and drawings".to_string()),
+            }
+        ))
+    );
+    let cfg = ParseConfig::default();
+    assert_eq!(
+        list(cfg, r#"[ 'photo'/'ph' : "Photographs", 'video'/'v' ]"#),
+        Ok((
+            "",
+            ListU(vec![
+                photo,
+                KeywordU {
+                    name: "video".to_string(),
+                    id: "v".to_string(),
+                    description: None,
+                }
+            ])
+        ))
+    );
+}
+
+#[test]
+fn parse_keyword_comment() {
+    let cfg = ParseConfig::default();
+    let photo = |description: Option<&str>| KeywordU {
+        name: "photo".to_string(),
+        id: "ph".to_string(),
+        description: description.map(str::to_string),
+    };
+    assert_eq!(
+        documented_keyword(cfg, "'photo'/'ph' # photographs\n, 'art'"),
+        Ok(("\n, 'art'", photo(Some("photographs"))))
+    );
+    // explicit descriptions win over comments
+    assert_eq!(
+        documented_keyword(cfg, r#"'photo'/'ph' : "Photographs" # note"#),
+        Ok((" # note", photo(Some("Photographs"))))
+    );
+    assert_eq!(
+        documented_keyword(cfg, "'photo'/'ph' #"),
+        Ok(("", photo(None)))
+    );
+    assert_eq!(
+        documented_keyword(cfg, "'photo'/'ph', 'art'"),
+        Ok((", 'art'", photo(None)))
+    );
+    assert_eq!(
+        list(cfg, "[ 'photo'/'ph' # photographs\n]"),
+        Ok(("", ListU(vec![photo(Some("photographs"))])))
+    );
 }
 
 #[test]
 fn parse_string() {
     assert_eq!(string(r#""abc""#), Ok(("", "abc".to_string())));
+    assert_eq!(
+        string(r#""a\"b\\c\nd\te" x"#),
+        Ok((" x", "a\"b\\c\nd\te".to_string()))
+    );
+    assert_eq!(
+        string(r#""a\q""#),
+        Err(Err::Failure(NomParseError::Custom(
+            SchemaParseError::InvalidEscape("\\q".to_string())
+        )))
+    );
+    assert!(string(r#""abc\""#).is_err());
+}
+
+#[test]
+fn unparse_round_trip() {
+    let input = r#"schema "-" "_" [ category "Media" (exactly 1) ['art', 'photo'/'ph' : "Photographs"], category "People" (at_least 0) ['nate']]"#;
+    let expr = parse(input).unwrap();
+    assert!(exprs_equivalent(&parse(&unparse(&expr)).unwrap(), &expr));
+
+    let tricky = FnU {
+        name: "schema".to_string(),
+        args: vec![
+            StringU("-".to_string()),
+            StringU("_".to_string()),
+            ListU(vec![FnU {
+                name: "category".to_string(),
+                args: vec![
+                    StringU("6\" vinyl\nside \\ A".to_string()),
+                    ListU(vec![KeywordU {
+                        name: "it's".to_string(),
+                        id: "tab\there".to_string(),
+                        description: Some("\"quoted\"".to_string()),
+                    }]),
+                ],
+            }]),
+        ],
+    };
+    let printed = unparse(&tricky);
+    assert!(printed.contains(r#""6\" vinyl\nside \\ A""#));
+    assert_eq!(parse(&printed), Ok(tricky));
+}
+
+#[test]
+fn equivalent_exprs() {
+    let compact =
+        parse(r#"schema "-" "_" [ category "Media" (exactly 1) ['art', 'photo'/'ph'] ]"#).unwrap();
+    let spread = parse(
+        r#"
+schema "-" "_" [
+    # every file is one kind of media
+    category "Media" (exactly 1) [
+        'art',
+        'photo'/'ph'
+    ]
+]
+"#,
+    )
+    .unwrap();
+    assert!(exprs_equivalent(&compact, &spread));
+    assert!(exprs_equivalent(&spread, &compact));
+
+    let other =
+        parse(r#"schema "-" "_" [ category "Media" (at_most 1) ['art', 'photo'/'ph'] ]"#).unwrap();
+    assert!(!exprs_equivalent(&compact, &other));
+    let fewer = parse(r#"schema "-" "_" [ category "Media" (exactly 1) ['art'] ]"#).unwrap();
+    assert!(!exprs_equivalent(&compact, &fewer));
+    assert!(!exprs_equivalent(&NatU(1), &StringU("1".to_string())));
 }
 
 #[test]
 fn parse_line_space0() {
-    assert_eq!(line_space0(""), Ok(("", "")));
-    assert_eq!(line_space0(" x"), Ok(("x", "")));
-    assert_eq!(line_space0("\n   \t x"), Ok(("x", "")));
+    let cfg = ParseConfig::default();
+    assert_eq!(line_space0(cfg, ""), Ok(("", "")));
+    assert_eq!(line_space0(cfg, " x"), Ok(("x", "")));
+    assert_eq!(line_space0(cfg, "\n   \t x"), Ok(("x", "")));
+    assert_eq!(line_space0(cfg, " # comment\n x"), Ok(("x", "")));
+    assert_eq!(line_space0(cfg, "# comment"), Ok(("", "")));
 }
 
 #[test]
@@ -432,3 +1151,102 @@ fn parse_sep_by1() {
         Ok(("123", vec!["a", "b", "c"]))
     );
 }
+
+#[test]
+fn parse_leading_zeros() {
+    let strict = ParseConfig {
+        reject_leading_zeros: true,
+        ..ParseConfig::default()
+    };
+    assert_eq!(nat(ParseConfig::default(), "007"), Ok(("", 7)));
+    assert_eq!(nat(strict, "0"), Ok(("", 0)));
+    assert_eq!(nat(strict, "70"), Ok(("", 70)));
+    assert_eq!(
+        nat(strict, "007"),
+        Err(Err::Failure(NomParseError::Custom(
+            SchemaParseError::LeadingZero("007".to_string())
+        )))
+    );
+    assert!(parse("exactly 007").is_ok());
+    assert_eq!(
+        parse_with("exactly 007", strict),
+        Err(SchemaParseError::LeadingZero("007".to_string()))
+    );
+}
+
+#[test]
+fn parse_nat_overflow() {
+    let cfg = ParseConfig::default();
+    assert_eq!(nat(cfg, "255"), Ok(("", 255)));
+    assert_eq!(
+        nat(cfg, "256"),
+        Err(Err::Failure(NomParseError::Custom(
+            SchemaParseError::NatOverflow {
+                value: "256".to_string(),
+                max: 255
+            }
+        )))
+    );
+    assert_eq!(
+        parse("(exactly 999)"),
+        Err(SchemaParseError::NatOverflow {
+            value: "999".to_string(),
+            max: 255
+        })
+    );
+}
+
+#[test]
+fn reparse_one_category() {
+    let src: String = format!(
+        "schema \"-\" \"_\"\n  [ {}\n  ]\n",
+        (0..50)
+            .map(|i| format!(
+                "category \"Cat{i}\" (at_least 0) ['a{i}', 'b{i}' # the b{i} keyword\n    ]"
+            ))
+            .collect::<Vec<String>>()
+            .join("\n  , ")
+    );
+    let old = parse_spanned(&src).unwrap();
+    let spans = &old.category_spans;
+    assert_eq!(spans.len(), 50);
+    assert!(src[spans[17].clone()].starts_with("category \"Cat17\""));
+    let at = src.find("'b17'").unwrap() + 1;
+    let edit = at..at + 3;
+    let splice = |src: &str| format!("{}photo/b17{}", &src[..edit.start], &src[edit.end..]);
+    let new_src = splice(&src);
+    let new = reparse(&old, &src, &new_src, edit.clone()).unwrap();
+    assert_eq!(new, parse_spanned(&new_src).unwrap());
+
+    let (FnU { args: old_args, .. }, FnU { args: new_args, .. }) = (&old.expr, &new.expr) else {
+        panic!("not a schema");
+    };
+    let (ListU(old_cats), ListU(new_cats)) = (&old_args[2], &new_args[2]) else {
+        panic!("no categories");
+    };
+    let changed: Vec<usize> = (0..old_cats.len())
+        .filter(|i| old_cats[*i] != new_cats[*i])
+        .collect();
+    assert_eq!(changed, vec![17]);
+
+    // the other categories aren't looked at again, so they can be anything
+    let mut garbled = src.clone();
+    for (_, span) in spans.iter().enumerate().filter(|(i, _)| *i != 17) {
+        garbled.replace_range(span.clone(), &"%".repeat(span.len()));
+    }
+    assert!(parse(&garbled).is_err());
+    assert_eq!(reparse(&old, &garbled, &splice(&garbled), edit), Ok(new));
+
+    // edits that break the category are reported the same way a full parse would
+    let at = src.find("'a3'").unwrap();
+    let broken = format!("{}]{}", &src[..at], &src[at..]);
+    assert_eq!(reparse(&old, &src, &broken, at..at), parse_spanned(&broken));
+
+    // edits across categories fall back to a full parse
+    let (start, end) = (src.find("'b3'").unwrap(), src.find("'a5'").unwrap());
+    let merged = format!("{}{}", &src[..start], &src[end..]);
+    assert_eq!(
+        reparse(&old, &src, &merged, start..end),
+        parse_spanned(&merged)
+    );
+}
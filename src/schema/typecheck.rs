@@ -1,16 +1,18 @@
 use super::{
-    Category,
+    Category, Delim,
     ExprU::{self, *},
-    Keyword, Requirement,
-    Requirement::*,
-    Schema, SchemaTypeCheckError,
+    Keyword, Requirement, RequirementConstructor, Schema, SchemaTypeCheckError,
     SchemaTypeCheckError::*,
+    REQUIREMENT_ALIASES, REQUIREMENT_KINDS,
 };
+use crate::filename::{EmptyMode, SaltSpec};
 use std::{collections::HashSet, fmt, result::Result as StdResult};
 use ExprT::*;
 
+/// a schema expression after typechecking, for tools that want more than the final `Schema`.
 #[derive(Clone, Debug, PartialEq, Eq)]
-enum ExprT {
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum ExprT {
     SchemaT(Schema),
     CategoryT((Category, Vec<Keyword>)),
     KeywordT(Keyword),
@@ -57,97 +59,191 @@ pub fn typecheck(expr: ExprU) -> Result<Schema> {
     }
 }
 
+/// typechecks any expression rather than only a top level schema.
+pub fn typecheck_expr(expr: ExprU) -> Result<ExprT> {
+    typecheck_(expr)
+}
+
 fn typecheck_(expr: ExprU) -> Result<ExprT> {
     match expr {
         NatU(x) => Ok(NatT(x)),
         StringU(x) => Ok(StringT(x)),
-        KeywordU { name, id } => Ok(KeywordT(Keyword { name, id })),
+        KeywordU {
+            name,
+            id,
+            description,
+        } => Ok(KeywordT(Keyword {
+            name,
+            id,
+            description,
+            draft: false,
+        })),
         ListU(xs) => {
             let xs = xs
                 .iter()
                 .map(|x| typecheck_(x.clone()))
                 .collect::<Result<Vec<ExprT>>>()?;
-            let mut types = HashSet::with_capacity(2);
-            for x in xs.clone() {
-                types.insert(type_of(&x));
-            }
-            let types = types.into_iter().collect::<Vec<Type>>();
-            match &types[..] {
-                // empty list can take on any type
-                [] => Ok(ListT(xs)),
-                // homogeneous
-                [_] => Ok(ListT(xs)),
-                // heterogenous
-                _ => Err(HeterogeneousList(types.clone())),
+            // every element must unify with every other element
+            match xs
+                .iter()
+                .try_fold(Type::Hole, |t, x| unify(&t, &type_of(x)))
+            {
+                Some(_) => Ok(ListT(xs)),
+                None => {
+                    let mut types = HashSet::with_capacity(2);
+                    for x in xs.iter() {
+                        types.insert(type_of(x));
+                    }
+                    Err(HeterogeneousList(types.into_iter().collect()))
+                }
             }
         }
         FnU { name, args } => match (name.as_str(), &args[..]) {
-            ("exactly", [NatU(x)]) => Ok(RequirementT(Exactly(*x))),
-            ("at_least", [NatU(x)]) => Ok(RequirementT(AtLeast(*x))),
-            ("at_most", [NatU(x)]) => Ok(RequirementT(AtMost(*x))),
+            (name, [NatU(x)]) if requirement_kind(name).is_some() => {
+                Ok(RequirementT(requirement_kind(name).unwrap()(*x)))
+            }
+            // categories without a requirement are optional
+            ("category", [name @ StringU(_), keywords @ ListU(_)]) => typecheck_(FnU {
+                name: "category".to_string(),
+                args: vec![
+                    name.clone(),
+                    FnU {
+                        name: "at_least".to_string(),
+                        args: vec![NatU(0)],
+                    },
+                    keywords.clone(),
+                ],
+            }),
             ("category", [StringU(name), req @ FnU { .. }, keywords @ ListU(_)]) => {
                 let req = typecheck_(req.clone())?;
                 let keywords = typecheck_(keywords.clone())?;
                 let t = type_of(&keywords);
-                match (req, t.clone(), keywords) {
-                    (RequirementT(requirement), Type::List(t), ListT(xs)) => {
-                        if let Type::Keyword = *t {
-                            let keywords: Vec<Keyword> = xs
-                                .into_iter()
-                                .map(|elem| match elem {
-                                    KeywordT(kw) => kw,
-                                    _ => panic!("unreachable"),
-                                })
-                                .collect();
-                            Ok(CategoryT((
-                                Category {
-                                    name: name.clone(),
-                                    requirement,
-                                },
-                                keywords,
-                            )))
-                        } else {
-                            Err(TypeMismatch {
-                                expected: Type::List(Box::new(Type::Keyword)),
-                                got: Type::List(t),
-                            })
+                let expected = Type::List(Box::new(Type::Keyword));
+                match (req, unify(&expected, &t), keywords) {
+                    (RequirementT(requirement), Some(_), ListT(xs)) => {
+                        let mut keywords: Vec<Keyword> = Vec::with_capacity(xs.len());
+                        for elem in xs {
+                            let kw = match elem {
+                                KeywordT(kw) => kw,
+                                _ => panic!("unreachable"),
+                            };
+                            match keywords.iter().find(|x| x.id == kw.id) {
+                                // exact duplicates are harmless so they are dropped
+                                Some(x) if *x == kw => (),
+                                Some(_) => {
+                                    return Err(DuplicateKeywordId {
+                                        category: name.clone(),
+                                        id: kw.id,
+                                    })
+                                }
+                                None => keywords.push(kw),
+                            }
                         }
+                        Ok(CategoryT((
+                            Category {
+                                name: name.clone(),
+                                requirement,
+                                freeform: false,
+                                intra_delim: None,
+                                repeatable: false,
+                            },
+                            keywords,
+                        )))
                     }
-                    _ => Err(TypeMismatch {
-                        expected: Type::List(Box::new(Type::Keyword)),
-                        got: t,
-                    }),
+                    _ => Err(TypeMismatch { expected, got: t }),
                 }
             }
+            ("freeform", [StringU(name), req @ FnU { .. }]) => match typecheck_(req.clone())? {
+                RequirementT(requirement) => Ok(CategoryT((
+                    Category {
+                        name: name.clone(),
+                        requirement,
+                        freeform: true,
+                        intra_delim: None,
+                        repeatable: false,
+                    },
+                    vec![],
+                ))),
+                x => Err(TypeMismatch {
+                    expected: Type::Requirement,
+                    got: type_of(&x),
+                }),
+            },
+            // a string after the requirement separates tags within the category
+            (kind @ ("category" | "freeform"), [name, req, StringU(intra), rest @ ..]) => {
+                let mut args = vec![name.clone(), req.clone()];
+                args.extend_from_slice(rest);
+                match typecheck_(FnU {
+                    name: kind.to_string(),
+                    args,
+                })? {
+                    CategoryT((cat, kws)) => Ok(CategoryT((
+                        Category {
+                            intra_delim: Some(Delim::from(intra.as_str())),
+                            ..cat
+                        },
+                        kws,
+                    ))),
+                    _ => panic!("unreachable"),
+                }
+            }
+            ("draft", [kw]) => match typecheck_(kw.clone())? {
+                KeywordT(kw) => Ok(KeywordT(Keyword { draft: true, ..kw })),
+                x => Err(TypeMismatch {
+                    expected: Type::Keyword,
+                    got: type_of(&x),
+                }),
+            },
+            ("repeatable", [cat @ FnU { .. }]) => match typecheck_(cat.clone())? {
+                CategoryT((cat, kws)) => Ok(CategoryT((
+                    Category {
+                        repeatable: true,
+                        ..cat
+                    },
+                    kws,
+                ))),
+                x => Err(TypeMismatch {
+                    expected: Type::Category,
+                    got: type_of(&x),
+                }),
+            },
             ("schema", [StringU(delim), StringU(empty), categories @ ListU(_)]) => {
                 let categories = typecheck_(categories.clone())?;
                 let t = type_of(&categories);
-                match (t.clone(), categories) {
-                    (Type::List(telem), ListT(xs)) => {
-                        if let Type::Category = *telem {
-                            let categories = xs
-                                .into_iter()
-                                .map(|x| match x {
-                                    CategoryT(c) => c,
-                                    _ => panic!("unreachable"),
-                                })
-                                .collect();
-                            Ok(SchemaT(Schema {
-                                delim: delim.clone(),
-                                empty: empty.clone(),
-                                categories,
-                            }))
-                        } else {
-                            Err(TypeMismatch {
-                                expected: Type::List(Box::new(Type::Category)),
-                                got: Type::List(telem),
+                let expected = Type::List(Box::new(Type::Category));
+                match (unify(&expected, &t), categories) {
+                    (Some(_), ListT(xs)) => {
+                        let categories = xs
+                            .into_iter()
+                            .map(|x| match x {
+                                CategoryT(c) => c,
+                                _ => panic!("unreachable"),
                             })
+                            .collect::<Vec<(Category, Vec<Keyword>)>>();
+                        if let Some(kw) = categories
+                            .iter()
+                            .flat_map(|(_, kws)| kws)
+                            .find(|kw| kw.id == *empty)
+                        {
+                            return Err(KeywordEqualsEmpty {
+                                keyword: kw.id.clone(),
+                            });
                         }
+                        Ok(SchemaT(Schema {
+                            delim: Delim::from(delim.as_str()),
+                            empty: empty.clone(),
+                            salt: SaltSpec::default(),
+                            labeled: false,
+                            escape_delim: false,
+                            empty_mode: EmptyMode::default(),
+                            require_any_tag: false,
+                            max_tags_per_category: None,
+                            case_insensitive: false,
+                            version: None,
+                            categories,
+                        }))
                     }
-                    _ => Err(TypeMismatch {
-                        expected: Type::List(Box::new(Type::Category)),
-                        got: t,
-                    }),
+                    _ => Err(TypeMismatch { expected, got: t }),
                 }
             }
             (name, args) => {
@@ -164,6 +260,17 @@ fn typecheck_(expr: ExprU) -> Result<ExprT> {
     }
 }
 
+fn requirement_kind(name: &str) -> Option<RequirementConstructor> {
+    let name = REQUIREMENT_ALIASES
+        .iter()
+        .find(|(alias, _)| *alias == name)
+        .map_or(name, |(_, name)| name);
+    REQUIREMENT_KINDS
+        .iter()
+        .find(|(n, _)| *n == name)
+        .map(|(_, mk)| *mk)
+}
+
 fn type_of(expr: &ExprT) -> Type {
     match expr {
         SchemaT(_) => Type::Schema,
@@ -172,10 +279,22 @@ fn type_of(expr: &ExprT) -> Type {
         NatT(_) => Type::Nat,
         StringT(_) => Type::String,
         KeywordT(_) => Type::Keyword,
-        ListT(args) => match &args[..] {
-            [] => Type::List(Box::new(Type::Hole)),
-            [h, _t @ ..] => Type::List(Box::new(type_of(h))),
-        },
+        // falls back to the first element's type for lists that don't unify
+        ListT(args) => Type::List(Box::new(
+            args.iter()
+                .try_fold(Type::Hole, |t, x| unify(&t, &type_of(x)))
+                .unwrap_or_else(|| args.first().map_or(Type::Hole, type_of)),
+        )),
+    }
+}
+
+/// a hole unifies with any type, and lists unify when their element types do.
+fn unify(a: &Type, b: &Type) -> Option<Type> {
+    match (a, b) {
+        (Type::Hole, t) | (t, Type::Hole) => Some(t.clone()),
+        (Type::List(a), Type::List(b)) => unify(a, b).map(|t| Type::List(Box::new(t))),
+        (a, b) if a == b => Some(a.clone()),
+        _ => None,
     }
 }
 
@@ -186,6 +305,7 @@ fn test_typecheck() {
         KeywordU {
             name: "boo".to_string(),
             id: "b".to_string(),
+            description: None,
         },
     ]));
     match hetero_list {
@@ -193,3 +313,292 @@ fn test_typecheck() {
         _ => panic!("heterogeneous lists are not allowed."),
     }
 }
+
+#[test]
+fn test_typecheck_empty_lists() {
+    let empty_category = FnU {
+        name: "category".to_string(),
+        args: vec![
+            StringU("Media".to_string()),
+            FnU {
+                name: "at_least".to_string(),
+                args: vec![NatU(0)],
+            },
+            ListU(vec![]),
+        ],
+    };
+    assert_eq!(
+        typecheck_(empty_category.clone()),
+        Ok(CategoryT((
+            Category {
+                name: "Media".to_string(),
+                requirement: Requirement::AtLeast(0),
+                freeform: false,
+                intra_delim: None,
+                repeatable: false,
+            },
+            vec![]
+        )))
+    );
+
+    let empty_schema = FnU {
+        name: "schema".to_string(),
+        args: vec![
+            StringU("-".to_string()),
+            StringU("_".to_string()),
+            ListU(vec![]),
+        ],
+    };
+    assert_eq!(
+        typecheck(empty_schema),
+        Ok(Schema {
+            delim: Delim::Char('-'),
+            empty: "_".to_string(),
+            salt: SaltSpec::default(),
+            labeled: false,
+            escape_delim: false,
+            empty_mode: EmptyMode::default(),
+            require_any_tag: false,
+            max_tags_per_category: None,
+            case_insensitive: false,
+            version: None,
+            categories: vec![],
+        })
+    );
+
+    // holes unify with nested lists too
+    assert!(typecheck_(ListU(vec![ListU(vec![]), ListU(vec![NatU(0)])])).is_ok());
+    assert!(typecheck_(ListU(vec![
+        ListU(vec![NatU(0)]),
+        ListU(vec![empty_category])
+    ]))
+    .is_err());
+}
+
+#[test]
+fn test_builtins_typecheck() {
+    let category = FnU {
+        name: "category".to_string(),
+        args: vec![
+            StringU("Media".to_string()),
+            FnU {
+                name: "exactly".to_string(),
+                args: vec![NatU(1)],
+            },
+            ListU(vec![KeywordU {
+                name: "art".to_string(),
+                id: "art".to_string(),
+                description: None,
+            }]),
+        ],
+    };
+    for name in super::BUILTINS {
+        let args = match *name {
+            "schema" => vec![
+                StringU("-".to_string()),
+                StringU("_".to_string()),
+                ListU(vec![category.clone()]),
+            ],
+            "category" => match &category {
+                FnU { args, .. } => args.clone(),
+                _ => unreachable!(),
+            },
+            "freeform" => vec![
+                StringU("Tags".to_string()),
+                FnU {
+                    name: "at_least".to_string(),
+                    args: vec![NatU(0)],
+                },
+            ],
+            "repeatable" => vec![category.clone()],
+            "draft" => vec![KeywordU {
+                name: "art".to_string(),
+                id: "art".to_string(),
+                description: None,
+            }],
+            _ => vec![NatU(1)],
+        };
+        let expr = FnU {
+            name: name.to_string(),
+            args,
+        };
+        assert!(
+            typecheck_(expr).is_ok(),
+            "builtin {name} failed to typecheck"
+        );
+    }
+}
+
+#[test]
+fn test_duplicate_keywords() {
+    let category = |keywords: Vec<(&str, &str)>| FnU {
+        name: "category".to_string(),
+        args: vec![
+            StringU("Media".to_string()),
+            FnU {
+                name: "exactly".to_string(),
+                args: vec![NatU(1)],
+            },
+            ListU(
+                keywords
+                    .into_iter()
+                    .map(|(name, id)| KeywordU {
+                        name: name.to_string(),
+                        id: id.to_string(),
+                        description: None,
+                    })
+                    .collect(),
+            ),
+        ],
+    };
+
+    match typecheck_(category(vec![
+        ("photo", "ph"),
+        ("art", "art"),
+        ("photo", "ph"),
+    ])) {
+        Ok(CategoryT((_, kws))) => assert_eq!(
+            kws.iter().map(|kw| kw.id.as_str()).collect::<Vec<_>>(),
+            vec!["ph", "art"]
+        ),
+        x => panic!("exact duplicates should be deduped. got {x:?}"),
+    }
+
+    assert_eq!(
+        typecheck_(category(vec![("photo", "ph"), ("phone", "ph")])),
+        Err(DuplicateKeywordId {
+            category: "Media".to_string(),
+            id: "ph".to_string()
+        })
+    );
+}
+
+#[test]
+fn test_default_requirement() {
+    let schema = super::compile_schema(r#"schema "-" "_" [ category "Tags" ['a', 'b'] ]"#).unwrap();
+    assert_eq!(
+        schema.categories[0].0,
+        Category {
+            name: "Tags".to_string(),
+            requirement: Requirement::AtLeast(0),
+            freeform: false,
+            intra_delim: None,
+            repeatable: false,
+        }
+    );
+    assert_eq!(schema.categories[0].1.len(), 2);
+}
+
+#[test]
+fn test_freeform() {
+    let schema = super::compile_schema(
+        r#"schema "-" "_" [ category "Media" (exactly 1) ['art'], freeform "Tags" (at_most 2) ]"#,
+    )
+    .unwrap();
+    assert_eq!(
+        schema.categories[1],
+        (
+            Category {
+                name: "Tags".to_string(),
+                requirement: Requirement::AtMost(2),
+                freeform: true,
+                intra_delim: None,
+                repeatable: false,
+            },
+            vec![]
+        )
+    );
+    assert!(super::compile_schema(r#"schema "-" "_" [ freeform "Tags" "x" ]"#).is_err());
+}
+
+#[test]
+fn test_intra_delim() {
+    let schema = super::compile_schema(
+        r#"schema "-" "_" [ category "Subject" (at_least 1) "+" ['plants', 'animals'], freeform "Tags" (at_most 2) "," ]"#,
+    )
+    .unwrap();
+    assert_eq!(schema.categories[0].0.intra_delim, Some(Delim::from("+")));
+    assert_eq!(schema.categories[0].1.len(), 2);
+    assert_eq!(schema.categories[1].0.intra_delim, Some(Delim::from(",")));
+    assert!(schema.categories[1].0.freeform);
+}
+
+#[test]
+fn test_repeatable() {
+    let schema = super::compile_schema(
+        r#"schema "-" "_" [ repeatable (category "Tags" ['a', 'b']), category "Media" (exactly 1) ['art'] ]"#,
+    )
+    .unwrap();
+    assert!(schema.categories[0].0.repeatable);
+    assert!(!schema.categories[1].0.repeatable);
+    assert_eq!(
+        typecheck_(FnU {
+            name: "repeatable".to_string(),
+            args: vec![FnU {
+                name: "exactly".to_string(),
+                args: vec![NatU(1)],
+            }],
+        }),
+        Err(TypeMismatch {
+            expected: Type::Category,
+            got: Type::Requirement,
+        })
+    );
+}
+
+#[test]
+fn test_requirement_aliases() {
+    for (alias, name) in [("min", "at_least"), ("max", "at_most")] {
+        let expr = |name: &str| FnU {
+            name: name.to_string(),
+            args: vec![NatU(2)],
+        };
+        assert_eq!(typecheck_(expr(alias)), typecheck_(expr(name)));
+        assert!(typecheck_(expr(alias)).is_ok());
+    }
+    let src = r#"schema "-" "_" [ category "Media" (min 2) ['a', 'b'], category "People" (max 1) ['c'] ]"#;
+    let schema = super::compile_schema(src).unwrap();
+    assert_eq!(
+        schema,
+        super::compile_schema(&src.replace("min", "at_least").replace("max", "at_most")).unwrap()
+    );
+    // unparse keeps whichever name was written
+    let unparsed = super::parse::unparse(&super::parse::parse(src).unwrap());
+    assert!(unparsed.contains("(min 2)") && unparsed.contains("(max 1)"));
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_typed_json() {
+    let expr = super::parse::parse("(exactly 1)").unwrap();
+    let typed = typecheck_expr(expr).unwrap();
+    assert_eq!(typed, RequirementT(Requirement::Exactly(1)));
+    assert_eq!(
+        serde_json::to_value(&typed).unwrap(),
+        serde_json::json!({ "RequirementT": { "Exactly": 1 } })
+    );
+    let category =
+        typecheck_expr(super::parse::parse(r#"category "A" (at_most 1) ['x']"#).unwrap()).unwrap();
+    assert_eq!(
+        serde_json::to_value(&category).unwrap()["CategoryT"][1][0]["id"],
+        serde_json::json!("x")
+    );
+}
+
+#[test]
+fn test_keyword_equals_empty() {
+    let expr = super::parse::parse(
+        r#"schema "-" "_" [ category "Media" (exactly 1) ['art', 'none'/'_'] ]"#,
+    )
+    .unwrap();
+    assert_eq!(
+        typecheck(expr),
+        Err(KeywordEqualsEmpty {
+            keyword: "_".to_string()
+        })
+    );
+    let expr =
+        super::parse::parse(r#"schema "-" "_" [ category "Media" (exactly 1) ['art', '__'] ]"#)
+            .unwrap();
+    assert!(typecheck(expr).is_ok());
+}
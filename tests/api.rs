@@ -0,0 +1,44 @@
+use nametag::{
+    compile_schema, generate, generate_with_salt, parse_schema, to_empty_state, typecheck,
+    Category, Delim, EmptyMode, Error, FilenameParseError, GenerateFilenameError, Keyword,
+    ParseOptions, PreparedSchema, Requirement, Result, SaltMode, SaltPosition, SaltSpec, Schema,
+    SchemaError, SchemaParseError, SchemaTypeCheckError, State,
+};
+
+const SCHEMA: &str = r#"schema "-" "_" [ category "Media" (exactly 1) ['art', 'photo'/'ph'] ]"#;
+
+#[test]
+fn crate_root_exports() {
+    let schema: Schema = typecheck(parse_schema(SCHEMA).unwrap()).unwrap();
+    assert_eq!(compile_schema(SCHEMA).ok(), Some(schema.clone()));
+
+    let mut state: State = to_empty_state(&schema);
+    state[0].1[1].1 = true;
+    let name = generate_with_salt(&schema, &state, "ABC123").unwrap();
+    assert_eq!(generate(&schema, &state).unwrap(), "ph");
+    let prepared = PreparedSchema::new(&schema);
+    assert_eq!(
+        prepared.parse_with(&name, &ParseOptions::default()),
+        Ok(state)
+    );
+
+    let (cat, kws): &(Category, Vec<Keyword>) = &schema.categories[0];
+    assert_eq!(cat.requirement, Requirement::Exactly(1));
+    assert_eq!(kws.len(), 2);
+    assert_eq!(schema.delim, Delim::Char('-'));
+    assert_eq!(schema.empty_mode, EmptyMode::Placeholder);
+    let salt: SaltSpec = schema.salt;
+    assert_eq!(
+        (salt.mode, salt.position),
+        (SaltMode::Random, SaltPosition::Leading)
+    );
+
+    let _: Option<FilenameParseError> = schema.parse("ABC123").err();
+    let _: Option<GenerateFilenameError> = generate(&schema, &to_empty_state(&schema)).err();
+    let _: Option<SchemaParseError> = parse_schema("schema [").err();
+    let _: Option<SchemaTypeCheckError> =
+        typecheck(parse_schema(r#"category "x" ['a']"#).unwrap()).err();
+    let _: Option<SchemaError> = schema.validate().err().and_then(|e| e.into_iter().next());
+    let _: Result<Schema> = compile_schema(SCHEMA);
+    let _: Option<Error> = compile_schema("schema [").err();
+}
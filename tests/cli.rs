@@ -0,0 +1,38 @@
+use std::{path::PathBuf, process::Command};
+
+fn empty_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("nametag-{name}-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn schema_inline() {
+    let dir = empty_dir("schema-inline");
+    let output = Command::new(env!("CARGO_BIN_EXE_nametag"))
+        .arg(&dir)
+        .arg("--schema-inline")
+        .arg(r#"schema "-" "_" [ category "Media" (exactly 1) ['art'], category "People" (at_least 0) ['nate'] ]"#)
+        .arg("--print-name")
+        .output()
+        .unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout).trim(), "art-_");
+}
+
+#[test]
+fn schema_inline_invalid() {
+    let dir = empty_dir("schema-inline-invalid");
+    let output = Command::new(env!("CARGO_BIN_EXE_nametag"))
+        .arg(&dir)
+        .arg("--schema-inline")
+        .arg(r#"schema "-" "_" [ category "Media" (exactly 1) ['art' ]"#)
+        .output()
+        .unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Unclosed '['"));
+}